@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+
+use fracas::{terrain::*, utils::*, world::*, Team};
+
+// two armies scattered over the whole field one to a cell, so every tick has plenty of moving and fighting
+fn battle(units: usize, width: i16, height: i16) -> World {
+    let rng = fastrand::Rng::with_seed(units as u64);
+    let mut world = World::with_seed(1);
+    world.set_terrain(Terrain::open(width, height));
+
+    let mut cells: Vec<(i16, i16)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .collect();
+    rng.shuffle(&mut cells);
+    for (n, (x, y)) in cells.into_iter().take(units).enumerate() {
+        let colour = if n % 2 == 0 { Team::Green } else { Team::Red };
+        let mut piece = match n % 3 {
+            0 => generate_barbarian(0, colour),
+            1 => generate_archer(0, colour),
+            _ => generate_giant(0, colour),
+        };
+        piece.x = x;
+        piece.y = y;
+        piece.movement_cooldown = 1;
+        world.spawn(piece);
+    }
+    world
+}
+
+fn tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tick");
+    // the bigger army gets a field as crowded as the classic one is with the smaller
+    for (units, width, height) in [(1_000, FIELD_WIDTH, FIELD_HEIGHT), (10_000, 220, 64)] {
+        group.bench_with_input(BenchmarkId::from_parameter(units), &units, |b, &units| {
+            b.iter_batched_ref(
+                || battle(units, width, height),
+                |world| world.step(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tick);
+criterion_main!(benches);
@@ -0,0 +1,362 @@
+use std::time::{Duration, Instant};
+
+use async_std::{net::TcpStream, task::spawn};
+use futures::channel::mpsc::{unbounded, TryRecvError};
+use futures_timer::Delay;
+use serde::{Deserialize, Serialize};
+
+use crate::catalogue::*;
+use crate::delta::*;
+use crate::map::*;
+use crate::net::*;
+use crate::*;
+
+// how far ahead on numbers the random and counter picking opponents let themselves get
+const LEAD: usize = 1;
+
+// how far ahead on strength the economy aware opponent gets before it starts saving instead
+const COMFORT: f32 = 1.2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    // anything, anywhere
+    Random,
+    // whatever does best against what the player has out, where the player's units are
+    Counter,
+    // counter picks too, the most for its gold, saving up while ahead and spending when behind
+    Economy,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Random, Difficulty::Counter, Difficulty::Economy];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Random => "random",
+            Difficulty::Counter => "counter",
+            Difficulty::Economy => "economy",
+        }
+    }
+
+    // the time between one decision and the next
+    pub fn think_interval(self) -> Duration {
+        match self {
+            Difficulty::Random => Duration::from_millis(2_000),
+            Difficulty::Counter => Duration::from_millis(1_500),
+            Difficulty::Economy => Duration::from_millis(1_000),
+        }
+    }
+}
+
+// the decisions of a computer player, kept apart from the connection so they can be tested
+pub struct Opponent {
+    difficulty: Difficulty,
+    team: Team,
+    catalogue: Catalogue,
+    map: Map,
+    rng: fastrand::Rng,
+    // what the server last said it has, less whatever has been spent since
+    gold: u32,
+}
+
+impl Opponent {
+    pub fn new(
+        difficulty: Difficulty,
+        team: Team,
+        catalogue: Catalogue,
+        map: Map,
+        seed: u64,
+    ) -> Opponent {
+        Opponent {
+            difficulty,
+            team,
+            catalogue,
+            map,
+            rng: fastrand::Rng::with_seed(seed),
+            gold: 0,
+        }
+    }
+
+    pub fn gold(&self) -> u32 {
+        self.gold
+    }
+
+    pub fn set_gold(&mut self, gold: u32) {
+        self.gold = gold;
+    }
+
+    // the spawns to send this time round, sent exactly as a human player's would be
+    pub fn think(&mut self, phase: MatchPhase, pieces: &[Character]) -> Vec<ClientMessage> {
+        if matches!(phase, MatchPhase::Finished(_)) || self.map.spawn_points(self.team).is_empty() {
+            return Vec::new();
+        }
+
+        let (ours, theirs): (Vec<&Character>, Vec<&Character>) = pieces
+            .iter()
+            .filter(|p| p.hp > 0)
+            .partition(|p| p.team == self.team);
+        let enemies: Vec<UnitDef> = theirs
+            .iter()
+            .filter_map(|p| self.unit_of(p).cloned())
+            .collect();
+
+        // nothing the server would refuse for the price
+        let picks: Vec<UnitDef> = match self.difficulty {
+            Difficulty::Random if ours.len() <= theirs.len() + LEAD => {
+                let gold = self.gold;
+                let affordable: Vec<&UnitDef> = self
+                    .catalogue
+                    .units
+                    .iter()
+                    .filter(|u| u.cost <= gold)
+                    .collect();
+                match affordable.len() {
+                    0 => Vec::new(),
+                    n => vec![affordable[self.rng.usize(..n)].clone()],
+                }
+            }
+            Difficulty::Counter if ours.len() <= theirs.len() + LEAD => self
+                .counter_pick(&enemies, self.gold)
+                .cloned()
+                .into_iter()
+                .collect(),
+            Difficulty::Economy => {
+                let mut mine = self.strength(&ours);
+                let yours = self.strength(&theirs);
+
+                // the best value there is, or nothing until it can be afforded
+                let pick = self.counter_pick(&enemies, u32::MAX).unwrap().clone();
+                let mut gold = self.gold;
+                let mut picks = Vec::new();
+                while mine <= yours * COMFORT && gold >= pick.cost {
+                    gold -= pick.cost;
+                    mine += worth(&pick, &self.catalogue);
+                    picks.push(pick.clone());
+                }
+                picks
+            }
+            _ => Vec::new(),
+        };
+
+        picks
+            .into_iter()
+            .map(|unit| {
+                self.gold -= unit.cost;
+                ClientMessage::SpawnUnit {
+                    row: self.row(&theirs),
+                    unit: unit.name,
+                }
+            })
+            .collect()
+    }
+
+    fn unit_of(&self, piece: &Character) -> Option<&UnitDef> {
+        self.catalogue
+            .units
+            .iter()
+            .find(|u| u.denotation == piece.denotation)
+    }
+
+    // the unit costing no more than the gold that does best against the enemies for what it is
+    // worth, or for what it costs to the economy aware opponent; anything will do against none
+    fn counter_pick(&self, enemies: &[UnitDef], gold: u32) -> Option<&UnitDef> {
+        let value = |unit: &UnitDef| {
+            let against: f32 = enemies.iter().map(|enemy| edge(unit, enemy)).sum();
+            let against = if enemies.is_empty() { 1.0 } else { against };
+            match self.difficulty {
+                Difficulty::Economy => against / unit.cost.max(1) as f32,
+                _ => against / worth(unit, &self.catalogue),
+            }
+        };
+        self.catalogue
+            .units
+            .iter()
+            .filter(|unit| unit.cost <= gold)
+            .reduce(|best, unit| {
+                if value(unit) > value(best) {
+                    unit
+                } else {
+                    best
+                }
+            })
+    }
+
+    // what an army is worth, counting only what it has left of each unit
+    fn strength(&self, pieces: &[&Character]) -> f32 {
+        pieces
+            .iter()
+            .filter_map(|p| {
+                let unit = self.unit_of(p)?;
+                Some(worth(unit, &self.catalogue) * p.hp as f32 / unit.hp.max(1) as f32)
+            })
+            .sum()
+    }
+
+    // the random opponent spawns anywhere, the others opposite the enemy's average row
+    fn row(&mut self, enemies: &[&Character]) -> u8 {
+        let points = self.map.spawn_points(self.team);
+        if self.difficulty == Difficulty::Random || enemies.is_empty() {
+            return self.rng.u8(1..=points.len() as u8);
+        }
+
+        let middle = enemies.iter().map(|p| p.y as f32).sum::<f32>() / enemies.len() as f32;
+        let nearest = (0..points.len())
+            .min_by(|&a, &b| {
+                let (da, db) = (
+                    (points[a].1 as f32 - middle).abs(),
+                    (points[b].1 as f32 - middle).abs(),
+                );
+                da.total_cmp(&db)
+            })
+            .unwrap();
+        nearest as u8 + 1
+    }
+}
+
+// the chance that 2d6 plus the skill reaches the defence, as the world rolls it
+pub fn hit_chance(attack_skill: i16, defence: i16) -> f32 {
+    let hits = (1..7)
+        .flat_map(|a| (1..7).map(move |b| a + b))
+        .filter(|roll| roll + attack_skill >= defence)
+        .count();
+    hits as f32 / 36.0
+}
+
+// expected damage per tick, once the two are in range of each other
+pub fn damage_rate(attacker: &UnitDef, defender: &UnitDef) -> f32 {
+    let range = &attacker.damage_range;
+    let damage = (range.start + range.end - 1) as f32 / 2.0;
+    hit_chance(attacker.attack_skill, defender.defence_class) * damage
+        / attacker.attack_rate.max(1) as f32
+}
+
+// above 1 when the unit tends to win a fight with the enemy, one on one on open ground;
+// the longer reach gets to shoot for as long as the other takes to walk the difference
+pub fn edge(unit: &UnitDef, enemy: &UnitDef) -> f32 {
+    let ticks_to_kill = |attacker: &UnitDef, defender: &UnitDef| {
+        defender.hp as f32 / damage_rate(attacker, defender).max(f32::EPSILON)
+    };
+    let head_start = (unit.attack_range - enemy.attack_range).max(0) * enemy.movement_rate;
+    let held_off = (enemy.attack_range - unit.attack_range).max(0) * unit.movement_rate;
+
+    (ticks_to_kill(enemy, unit) + head_start as f32)
+        / (ticks_to_kill(unit, enemy) + held_off as f32)
+}
+
+// how good the unit is against the whole catalogue, the geometric mean of its edges
+pub fn worth(unit: &UnitDef, catalogue: &Catalogue) -> f32 {
+    let logs: f32 = catalogue
+        .units
+        .iter()
+        .map(|enemy| edge(unit, enemy).ln())
+        .sum();
+    (logs / catalogue.units.len().max(1) as f32).exp()
+}
+
+// plays whichever side the server gives it in the session, until the server or the session goes away,
+// and then says why it stopped
+pub async fn play(address: String, code: String, difficulty: Difficulty) -> String {
+    let mut stream = match TcpStream::connect(&address).await {
+        Ok(stream) => stream,
+        Err(e) => return format!("The computer could not reach {address}: {e}"),
+    };
+    let name = format!("Computer ({})", difficulty.name());
+    for message in [
+        ClientMessage::JoinGame { code: code.clone() },
+        ClientMessage::SetName { name },
+    ] {
+        if let Err(e) = send_message(&mut stream, &message).await {
+            return format!("The computer lost its connection: {e}");
+        }
+    }
+
+    let (pushed, mut incoming) = unbounded();
+    let reading = spawn(receive_messages(stream.clone(), pushed));
+
+    let mut decoder = DeltaDecoder::default();
+    let mut pieces: Vec<Character> = Vec::new();
+    let mut phase = MatchPhase::Lobby;
+    let mut opponent: Option<Opponent> = None;
+    let mut next_think = Instant::now();
+
+    loop {
+        Delay::new(Duration::from_millis(100)).await;
+
+        let mut messages = Vec::new();
+        loop {
+            match incoming.try_recv() {
+                Ok(ServerMessage::GameJoined {
+                    team,
+                    phase: p,
+                    catalogue,
+                    map,
+                    ..
+                }) => {
+                    opponent = Some(Opponent::new(
+                        difficulty,
+                        team,
+                        catalogue,
+                        map,
+                        fastrand::u64(..),
+                    ));
+                    phase = p;
+                    if phase == MatchPhase::Lobby {
+                        messages.push(ClientMessage::Ready);
+                    }
+                }
+                // ready again for every rematch, the server hands out fresh gold for it
+                Ok(ServerMessage::Phase(p)) => {
+                    if p == MatchPhase::Lobby && phase != MatchPhase::Lobby {
+                        messages.push(ClientMessage::Ready);
+                    }
+                    phase = p;
+                }
+                // a refused spawn says what there really is to spend, spent on the next think
+                Ok(ServerMessage::Gold { gold, .. })
+                | Ok(ServerMessage::Error(ServerError::NotEnoughGold { gold, .. })) => {
+                    if let Some(opponent) = opponent.as_mut() {
+                        opponent.set_gold(gold);
+                    }
+                }
+                Ok(ServerMessage::Snapshot {
+                    tick, pieces: p, ..
+                }) => {
+                    pieces = decoder.keyframe(tick, p).to_vec();
+                }
+                Ok(ServerMessage::Delta {
+                    baseline,
+                    tick,
+                    delta,
+                }) => {
+                    if let Some(p) = decoder.delta(baseline, tick, &delta) {
+                        pieces = p.to_vec();
+                    }
+                }
+                Ok(ServerMessage::Error(ServerError::UnknownSession(_))) => {
+                    return format!("The computer could not find session {code}");
+                }
+                Err(TryRecvError::Closed) => {
+                    return match reading.await {
+                        Ok(()) => "The computer lost its connection to the server".to_string(),
+                        Err(e) => format!("The computer lost its connection: {e}"),
+                    };
+                }
+                Ok(_) => (),
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        if let Some(tick) = decoder.latest_tick() {
+            messages.push(ClientMessage::Acknowledge { tick });
+        }
+        if let Some(opponent) = opponent.as_mut().filter(|_| Instant::now() >= next_think) {
+            messages.extend(opponent.think(phase, &pieces));
+            next_think = Instant::now() + difficulty.think_interval();
+        }
+        for message in messages {
+            if let Err(e) = send_message(&mut stream, &message).await {
+                return format!("The computer lost its connection: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use std::{fmt, thread};
+
+use serde::Serialize;
+
+use crate::catalogue::*;
+use crate::events::*;
+use crate::map::*;
+use crate::utils::*;
+use crate::world::*;
+use crate::Team;
+
+// a battle still going after this many ticks is called off as a stalemate
+pub const MAX_TICKS: u64 = 100_000;
+
+// the most one side can field, both together stay well inside the ids a world can hand out
+pub const MAX_ARMY: usize = 10_000;
+
+// how many of each unit one side fields, by catalogue name
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Army {
+    pub units: Vec<(usize, String)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArmyError {
+    // the matchup has to be two armies with "vs" between them
+    NotAMatchup,
+    Empty,
+    UnknownUnit(String),
+    // more than MAX_ARMY units, this many
+    TooBig(usize),
+}
+
+impl fmt::Display for ArmyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArmyError::NotAMatchup => write!(
+                f,
+                "expected two armies, like \"5 barbarians vs 2 giants + 3 archers\""
+            ),
+            ArmyError::Empty => write!(f, "an army needs at least one unit"),
+            ArmyError::UnknownUnit(name) => write!(f, "no unit called {name} in the catalogue"),
+            ArmyError::TooBig(count) => {
+                write!(f, "an army can field up to {MAX_ARMY} units, not {count}")
+            }
+        }
+    }
+}
+
+impl Army {
+    // "2 giants + 3 archers", names as in the catalogue, plural or not, and a missing count is 1
+    pub fn parse(text: &str, catalogue: &Catalogue) -> Result<Army, ArmyError> {
+        let mut units: Vec<(usize, String)> = Vec::new();
+        for part in text
+            .split(['+', ','])
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        {
+            let (count, name) = match part.split_once(char::is_whitespace) {
+                Some((count, name)) if count.parse::<usize>().is_ok() => {
+                    (count.parse().unwrap(), name.trim())
+                }
+                _ => (1, part),
+            };
+            let name = name.to_lowercase();
+            let unit = [
+                Some(name.as_str()),
+                name.strip_suffix('s'),
+                name.strip_suffix("es"),
+            ]
+            .into_iter()
+            .flatten()
+            .find_map(|n| catalogue.unit(n))
+            .ok_or(ArmyError::UnknownUnit(name.clone()))?;
+
+            match units.iter_mut().find(|(_, n)| *n == unit.name) {
+                Some((n, _)) => *n = n.saturating_add(count),
+                None => units.push((count, unit.name.clone())),
+            }
+        }
+        units.retain(|(count, _)| *count > 0);
+        if units.is_empty() {
+            return Err(ArmyError::Empty);
+        }
+        let total = units
+            .iter()
+            .fold(0usize, |total, (count, _)| total.saturating_add(*count));
+        if total > MAX_ARMY {
+            return Err(ArmyError::TooBig(total));
+        }
+        Ok(Army { units })
+    }
+
+    // green's army, then red's
+    pub fn matchup(text: &str, catalogue: &Catalogue) -> Result<(Army, Army), ArmyError> {
+        let (green, red) = text.split_once(" vs ").ok_or(ArmyError::NotAMatchup)?;
+        Ok((Army::parse(green, catalogue)?, Army::parse(red, catalogue)?))
+    }
+}
+
+impl fmt::Display for Army {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<String> = self
+            .units
+            .iter()
+            .map(|(count, name)| format!("{count} {name}"))
+            .collect();
+        write!(f, "{}", parts.join(" + "))
+    }
+}
+
+// everything a batch of battles is fought under
+pub struct Simulation {
+    pub green: Army,
+    pub red: Army,
+    pub catalogue: Catalogue,
+    pub map: Map,
+    pub win_condition: WinCondition,
+    pub battles: u64,
+    // battle n is fought with seed + n, so any one of them can be fought again on its own
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BattleResult {
+    pub seed: u64,
+    // None for a stalemate
+    pub winner: Option<String>,
+    pub ticks: u64,
+    // what each side has left between all its units
+    pub green_hp: i32,
+    pub red_hp: i32,
+    // by the report's units, in the same order
+    pub damage: Vec<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct UnitStats {
+    pub team: String,
+    pub unit: String,
+    pub count: usize,
+    // per battle, between every unit of the kind on that side
+    pub damage_dealt: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub green: Army,
+    pub red: Army,
+    pub map: String,
+    pub seed: u64,
+    pub battles: u64,
+    pub green_wins: u64,
+    pub red_wins: u64,
+    pub draws: u64,
+    pub stalemates: u64,
+    pub average_ticks: f64,
+    pub average_green_hp: f64,
+    pub average_red_hp: f64,
+    pub units: Vec<UnitStats>,
+    #[serde(skip)]
+    pub results: Vec<BattleResult>,
+}
+
+impl Simulation {
+    // battles are independent of each other, so they are shared out over every core there is
+    pub fn run(&self) -> Report {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get()) as u64;
+        let per_thread = self.battles.div_ceil(threads.max(1));
+
+        let results: Vec<BattleResult> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..self.battles)
+                .step_by(per_thread.max(1) as usize)
+                .map(|start| {
+                    let end = (start + per_thread).min(self.battles);
+                    scope.spawn(move || {
+                        (start..end)
+                            .map(|n| self.fight(self.seed.wrapping_add(n)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+
+        self.report(results)
+    }
+
+    // one battle: both armies spread over their side's spawn points in order, then left to it
+    pub fn fight(&self, seed: u64) -> BattleResult {
+        let mut world = World::with_seed(seed);
+        world.set_terrain(self.map.tiles.clone());
+
+        // the unique_ids the world hands out, each one's slot in the report's units
+        let mut slots: Vec<usize> = Vec::new();
+        for (team, army) in [(Team::Green, &self.green), (Team::Red, &self.red)] {
+            let points = self.map.spawn_points(team);
+            let mut next = 0;
+            for (count, name) in &army.units {
+                let unit = self.catalogue.unit(name).unwrap();
+                let slot = self.slot(team, name);
+                for _ in 0..*count {
+                    let (x, y) = points[next % points.len()];
+                    next += 1;
+                    // only an army put together by hand past MAX_ARMY runs out of ids
+                    let Some(id) = world.spawn(generate_unit(unit, x, y, team)) else {
+                        break;
+                    };
+                    if slots.len() <= id as usize {
+                        slots.resize(id as usize + 1, 0);
+                    }
+                    slots[id as usize] = slot;
+                }
+            }
+        }
+
+        let mut tally = Tally {
+            damage: vec![0; self.green.units.len() + self.red.units.len()],
+            slots,
+        };
+        let outcome = loop {
+            if let Some(outcome) = world.outcome(self.win_condition) {
+                break Some(outcome);
+            }
+            if world.tick() >= MAX_TICKS {
+                break None;
+            }
+            world.step();
+            tally.record(world.tick(), &world.take_events());
+        };
+
+        let hp = |team| {
+            world
+                .living()
+                .filter(|p| p.team == team)
+                .map(|p| p.hp as i32)
+                .sum()
+        };
+        BattleResult {
+            seed,
+            winner: outcome.map(|outcome| match outcome {
+                Outcome::Won(team) => team_name(team),
+                Outcome::Draw => "draw".to_string(),
+            }),
+            ticks: world.tick(),
+            green_hp: hp(Team::Green),
+            red_hp: hp(Team::Red),
+            damage: tally.damage,
+        }
+    }
+
+    fn slot(&self, team: Team, name: &str) -> usize {
+        let green = self.green.units.iter().position(|(_, n)| n == name);
+        match team {
+            Team::Green => green.unwrap(),
+            Team::Red => {
+                self.green.units.len() + self.red.units.iter().position(|(_, n)| n == name).unwrap()
+            }
+        }
+    }
+
+    fn report(&self, results: Vec<BattleResult>) -> Report {
+        let battles = results.len().max(1) as f64;
+        let wins = |name: &str| {
+            results
+                .iter()
+                .filter(|r| r.winner.as_deref() == Some(name))
+                .count() as u64
+        };
+        let average =
+            |value: &dyn Fn(&BattleResult) -> f64| results.iter().map(value).sum::<f64>() / battles;
+
+        let sides = [(Team::Green, &self.green), (Team::Red, &self.red)];
+        let units = sides
+            .iter()
+            .flat_map(|(team, army)| {
+                army.units
+                    .iter()
+                    .map(move |(count, unit)| (*team, *count, unit))
+            })
+            .enumerate()
+            .map(|(slot, (team, count, unit))| UnitStats {
+                team: team_name(team),
+                unit: unit.clone(),
+                count,
+                damage_dealt: average(&|r| r.damage[slot] as f64),
+            })
+            .collect();
+
+        Report {
+            green: self.green.clone(),
+            red: self.red.clone(),
+            map: self.map.name.clone(),
+            seed: self.seed,
+            battles: results.len() as u64,
+            green_wins: wins("green"),
+            red_wins: wins("red"),
+            draws: wins("draw"),
+            stalemates: results.iter().filter(|r| r.winner.is_none()).count() as u64,
+            average_ticks: average(&|r| r.ticks as f64),
+            average_green_hp: average(&|r| r.green_hp as f64),
+            average_red_hp: average(&|r| r.red_hp as f64),
+            units,
+            results,
+        }
+    }
+}
+
+impl Report {
+    pub fn win_rate(&self, wins: u64) -> f64 {
+        wins as f64 / self.battles.max(1) as f64
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports always serialize")
+    }
+
+    // one line per battle, for anyone who wants to dig further than the averages
+    pub fn to_csv(&self) -> String {
+        let mut header = vec!["seed", "winner", "ticks", "green_hp", "red_hp"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<String>>();
+        header.extend(
+            self.units
+                .iter()
+                .map(|u| format!("{}_{}_damage", u.team, u.unit)),
+        );
+
+        let mut csv = header.join(",") + "\n";
+        for r in &self.results {
+            let mut row = vec![
+                r.seed.to_string(),
+                r.winner.clone().unwrap_or_else(|| "stalemate".to_string()),
+                r.ticks.to_string(),
+                r.green_hp.to_string(),
+                r.red_hp.to_string(),
+            ];
+            row.extend(r.damage.iter().map(|d| d.to_string()));
+            csv += &(row.join(",") + "\n");
+        }
+        csv
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} vs {} on {}, {} battles from seed {}",
+            self.green, self.red, self.map, self.battles, self.seed
+        )?;
+        writeln!(f)?;
+        for (name, wins) in [
+            ("green", self.green_wins),
+            ("red", self.red_wins),
+            ("draw", self.draws),
+            ("stalemate", self.stalemates),
+        ] {
+            writeln!(
+                f,
+                "{name:<10} {wins:>7}  {:>6.1}%",
+                self.win_rate(wins) * 100.0
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "average length     {:.0} ticks", self.average_ticks)?;
+        writeln!(f, "green hp left      {:.1}", self.average_green_hp)?;
+        writeln!(f, "red hp left        {:.1}", self.average_red_hp)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<6} {:<12} {:>5} {:>16}",
+            "side", "unit", "count", "damage / battle"
+        )?;
+        for u in &self.units {
+            writeln!(
+                f,
+                "{:<6} {:<12} {:>5} {:>16.1}",
+                u.team, u.unit, u.count, u.damage_dealt
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn team_name(team: Team) -> String {
+    format!("{:?}", team).to_lowercase()
+}
+
+// the damage each slot in the report has dealt
+struct Tally {
+    damage: Vec<i32>,
+    // by unique_id
+    slots: Vec<usize>,
+}
+
+impl EventSink for Tally {
+    fn record(&mut self, _tick: u64, events: &[CombatEvent]) {
+        for event in events {
+            if let CombatEvent::Hit {
+                attacker, damage, ..
+            } = event
+            {
+                self.damage[self.slots[attacker.unique_id as usize]] += *damage as i32;
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::{env, path::PathBuf, process::exit};
+
+use fracas::{balance::*, catalogue::Catalogue, map::Map, world::WinCondition};
+
+const USAGE: &str = "usage: fracas-balance \"<army> vs <army>\" [--battles <number>] [--seed <number>] [--time-limit <ticks>] [--units <path>] [--map <path>] [--format text|csv|json]\n  e.g.  fracas-balance \"5 barbarians vs 2 giants + 3 archers\" --battles 5000";
+
+enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+struct Options {
+    simulation: Simulation,
+    format: Format,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut matchup: Option<String> = None;
+    let mut battles = 1_000;
+    let mut seed = fastrand::u64(..);
+    let mut win_condition = WinCondition::LastTeamStanding;
+    let mut catalogue = Catalogue::built_in().clone();
+    let mut map = Map::built_in().clone();
+    let mut format = Format::Text;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+
+        match arg.as_str() {
+            "--battles" => {
+                battles = value()?
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or("--battles must be a positive number")?
+            }
+            "--seed" => {
+                seed = value()?
+                    .parse()
+                    .map_err(|_| "--seed must be a number".to_string())?
+            }
+            "--time-limit" => {
+                let ticks = value()?
+                    .parse()
+                    .ok()
+                    .filter(|ticks| *ticks > 0)
+                    .ok_or("--time-limit must be a positive number of ticks")?;
+                win_condition = WinCondition::TimeLimit(ticks);
+            }
+            "--units" => {
+                let path = PathBuf::from(value()?);
+                catalogue =
+                    Catalogue::load(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            }
+            "--map" => {
+                let path = PathBuf::from(value()?);
+                map = Map::load(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            }
+            "--format" => {
+                format = match value()?.as_str() {
+                    "text" => Format::Text,
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    _ => return Err("--format must be text, csv or json".to_string()),
+                }
+            }
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                exit(0);
+            }
+            _ if matchup.is_none() && !arg.starts_with("--") => matchup = Some(arg),
+            _ => return Err(format!("unknown argument {arg}")),
+        }
+    }
+
+    // the units are only known once any --units file has been read
+    let matchup = matchup.ok_or("which armies should fight?")?;
+    let (green, red) = Army::matchup(&matchup, &catalogue).map_err(|e| e.to_string())?;
+
+    Ok(Options {
+        simulation: Simulation {
+            green,
+            red,
+            catalogue,
+            map,
+            win_condition,
+            battles,
+            seed,
+        },
+        format,
+    })
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            exit(2);
+        }
+    };
+
+    let report = options.simulation.run();
+    match options.format {
+        Format::Text => print!("{report}"),
+        Format::Csv => print!("{}", report.to_csv()),
+        Format::Json => println!("{}", report.to_json()),
+    }
+}
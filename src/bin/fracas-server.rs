@@ -0,0 +1,144 @@
+use std::{env, fs::OpenOptions, path::PathBuf, process::exit};
+
+use async_std::{net::TcpListener, task::block_on};
+
+use fracas::{catalogue::Catalogue, map::Map, server::*, world::WinCondition};
+
+const USAGE: &str = "usage: fracas-server [--bind <address>] [--port <port>] [--tick-rate <ticks per second>] [--max-catch-up <ticks>] [--seed <number>] [--countdown <seconds>] [--time-limit <seconds>] [--gold <starting gold>] [--income <gold per second>] [--units <path>] [--maps <dir>] [--replays <dir>] [--log <path>]";
+
+struct Options {
+    bind: String,
+    port: u16,
+    config: ServerConfig,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        bind: "0.0.0.0".to_string(),
+        port: 7777,
+        config: ServerConfig::default(),
+    };
+    // in seconds until the tick rate is known
+    let mut time_limit: Option<u64> = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+
+        match arg.as_str() {
+            "--bind" => options.bind = value()?,
+            "--port" => {
+                options.port = value()?
+                    .parse()
+                    .map_err(|_| "--port must be a number from 0 to 65535".to_string())?
+            }
+            "--tick-rate" => {
+                options.config.tick_rate = value()?
+                    .parse()
+                    .ok()
+                    .filter(|rate| *rate > 0)
+                    .ok_or("--tick-rate must be a positive number")?
+            }
+            "--max-catch-up" => {
+                options.config.max_catch_up = value()?
+                    .parse()
+                    .map_err(|_| "--max-catch-up must be a number".to_string())?
+            }
+            "--seed" => {
+                options.config.seed = Some(
+                    value()?
+                        .parse()
+                        .map_err(|_| "--seed must be a number".to_string())?,
+                )
+            }
+            "--countdown" => {
+                options.config.countdown_secs = value()?
+                    .parse()
+                    .map_err(|_| "--countdown must be a number of seconds".to_string())?
+            }
+            "--time-limit" => {
+                time_limit = Some(
+                    value()?
+                        .parse()
+                        .ok()
+                        .filter(|secs| *secs > 0)
+                        .ok_or("--time-limit must be a positive number of seconds")?,
+                )
+            }
+            "--gold" => {
+                options.config.starting_gold = value()?
+                    .parse()
+                    .map_err(|_| "--gold must be a number".to_string())?
+            }
+            "--income" => {
+                options.config.income = value()?
+                    .parse()
+                    .map_err(|_| "--income must be a number of gold a second".to_string())?
+            }
+            "--units" => {
+                let path = PathBuf::from(value()?);
+                options.config.catalogue =
+                    Catalogue::load(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+            }
+            "--maps" => {
+                let dir = PathBuf::from(value()?);
+                options.config.maps =
+                    Map::load_dir(&dir).map_err(|(path, e)| format!("{}: {e}", path.display()))?;
+            }
+            "--replays" => options.config.replay_dir = Some(PathBuf::from(value()?)),
+            // found out now rather than with the first line logged
+            "--log" => {
+                let path = PathBuf::from(value()?);
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| format!("{}: {e}", path.display()))?;
+                options.config.log_path = path;
+            }
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                exit(0);
+            }
+            _ => return Err(format!("unknown argument {arg}")),
+        }
+    }
+
+    if let Some(secs) = time_limit {
+        let ticks = secs * options.config.tick_rate as u64;
+        options.config.win_condition = WinCondition::TimeLimit(ticks);
+    }
+
+    Ok(options)
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            exit(2);
+        }
+    };
+
+    block_on(async {
+        let address = format!("{}:{}", options.bind, options.port);
+        let listener = match TcpListener::bind(&address).await {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("could not listen on {address}: {e}");
+                exit(1);
+            }
+        };
+
+        println!(
+            "fracas-server listening on {} at {} ticks per second with {} units on {} maps, logging to {}",
+            listener.local_addr().unwrap(),
+            options.config.tick_rate,
+            options.config.catalogue.units.len(),
+            options.config.maps.len(),
+            options.config.log_path.display()
+        );
+
+        server(listener, options.config).await;
+    });
+}
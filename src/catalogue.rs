@@ -0,0 +1,132 @@
+use std::{collections::HashSet, fmt, fs, io, ops::Range, path::Path, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+// the catalogue shipped with the game, used whenever no other file is given
+pub const BUILT_IN: &str = include_str!("../units.ron");
+
+// keys the client already uses for its own commands
+pub const RESERVED_KEYS: [char; 3] = ['q', 'r', 't'];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UnitDef {
+    pub name: String,
+    pub key: char,
+    pub denotation: char,
+    pub hp: i16,
+    pub attack_skill: i16,
+    pub damage_range: Range<i16>,
+    pub defence_class: i16,
+    pub attack_range: i16,
+    pub attack_rate: i16,
+    pub movement_rate: i16,
+    // catalogues written before there was gold field everything for free
+    #[serde(default)]
+    pub cost: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Catalogue {
+    pub units: Vec<UnitDef>,
+}
+
+#[derive(Debug)]
+pub enum CatalogueError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    // the file parsed but describes units the game cannot use
+    Invalid(String),
+}
+
+impl fmt::Display for CatalogueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CatalogueError::Io(e) => write!(f, "could not read the unit catalogue: {e}"),
+            CatalogueError::Parse(e) => write!(f, "could not parse the unit catalogue: {e}"),
+            CatalogueError::Invalid(reason) => write!(f, "bad unit catalogue: {reason}"),
+        }
+    }
+}
+
+impl Catalogue {
+    pub fn built_in() -> &'static Catalogue {
+        static CATALOGUE: OnceLock<Catalogue> = OnceLock::new();
+        CATALOGUE.get_or_init(|| Catalogue::parse(BUILT_IN).expect("units.ron is broken"))
+    }
+
+    pub fn load(path: &Path) -> Result<Catalogue, CatalogueError> {
+        let text = fs::read_to_string(path).map_err(CatalogueError::Io)?;
+        Catalogue::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Catalogue, CatalogueError> {
+        let catalogue: Catalogue = ron::from_str(text).map_err(CatalogueError::Parse)?;
+        catalogue.validate().map_err(CatalogueError::Invalid)?;
+        Ok(catalogue)
+    }
+
+    pub fn unit(&self, name: &str) -> Option<&UnitDef> {
+        self.units.iter().find(|u| u.name == name)
+    }
+
+    pub fn by_key(&self, key: char) -> Option<&UnitDef> {
+        self.units.iter().find(|u| u.key == key)
+    }
+
+    // how a unit on the field is told apart from the others, so no two share one
+    pub fn by_denotation(&self, denotation: char) -> Option<&UnitDef> {
+        self.units.iter().find(|u| u.denotation == denotation)
+    }
+
+    // every unit needs a key of its own to pick it, a letter of its own on the field, and stats
+    // the dice can work with
+    fn validate(&self) -> Result<(), String> {
+        if self.units.is_empty() {
+            return Err("there are no units in it".to_string());
+        }
+
+        let mut names = HashSet::new();
+        let mut keys = HashSet::new();
+        let mut denotations = HashSet::new();
+        for unit in &self.units {
+            let name = &unit.name;
+            if name.is_empty() {
+                return Err("a unit has no name".to_string());
+            }
+            if !names.insert(name) {
+                return Err(format!("{name} is listed twice"));
+            }
+            if !unit.key.is_ascii_lowercase() || RESERVED_KEYS.contains(&unit.key) {
+                return Err(format!(
+                    "{name} needs a lowercase key other than {RESERVED_KEYS:?}"
+                ));
+            }
+            if !keys.insert(unit.key) {
+                return Err(format!("{name} shares its key {:?}", unit.key));
+            }
+            if unit.denotation.is_whitespace() || unit.denotation.is_control() {
+                return Err(format!("{name} needs a visible denotation"));
+            }
+            if !denotations.insert(unit.denotation) {
+                return Err(format!(
+                    "{name} shares its denotation {:?}",
+                    unit.denotation
+                ));
+            }
+            if unit.hp <= 0 {
+                return Err(format!("{name} needs some hp"));
+            }
+            if unit.damage_range.is_empty() || unit.damage_range.start < 0 {
+                return Err(format!(
+                    "{name} has a damage range of {:?}, it needs at least one value and no negatives",
+                    unit.damage_range
+                ));
+            }
+            if unit.attack_range < 0 || unit.attack_rate < 0 || unit.movement_rate < 0 {
+                return Err(format!("{name} has a negative range or rate"));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::terrain::*;
+use crate::*;
+
+// send a full snapshot every this many ticks even when the client is keeping up
+pub const KEYFRAME_INTERVAL: u64 = 100;
+
+// how many past snapshots either side remembers to diff against
+pub const SNAPSHOT_HISTORY_LEN: usize = 128;
+
+// only the fields that change during a battle, anything unchanged is left as None
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CharacterDelta {
+    pub unique_id: u16,
+    pub position: Option<(i16, i16)>,
+    pub hp: Option<i16>,
+    pub is_attacking: Option<bool>,
+    pub attack_cooldown: Option<i16>,
+    pub movement_cooldown: Option<i16>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PiecesDelta {
+    pub added: Vec<Character>,
+    pub changed: Vec<CharacterDelta>,
+    pub removed: Vec<u16>,
+}
+
+impl CharacterDelta {
+    fn between(old: &Character, new: &Character) -> Option<CharacterDelta> {
+        let delta = CharacterDelta {
+            unique_id: new.unique_id,
+            position: changed((old.x, old.y), (new.x, new.y)),
+            hp: changed(old.hp, new.hp),
+            is_attacking: changed(old.is_attacking, new.is_attacking),
+            attack_cooldown: changed(old.attack_cooldown, new.attack_cooldown),
+            movement_cooldown: changed(old.movement_cooldown, new.movement_cooldown),
+        };
+
+        if delta
+            == (CharacterDelta {
+                unique_id: new.unique_id,
+                ..Default::default()
+            })
+        {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+
+    fn apply(&self, piece: &mut Character) {
+        if let Some((x, y)) = self.position {
+            piece.x = x;
+            piece.y = y;
+        }
+        if let Some(hp) = self.hp {
+            piece.hp = hp;
+        }
+        if let Some(is_attacking) = self.is_attacking {
+            piece.is_attacking = is_attacking;
+        }
+        if let Some(attack_cooldown) = self.attack_cooldown {
+            piece.attack_cooldown = attack_cooldown;
+        }
+        if let Some(movement_cooldown) = self.movement_cooldown {
+            piece.movement_cooldown = movement_cooldown;
+        }
+    }
+}
+
+fn changed<T: PartialEq>(old: T, new: T) -> Option<T> {
+    if old == new {
+        None
+    } else {
+        Some(new)
+    }
+}
+
+pub fn diff(baseline: &[Character], current: &[Character]) -> PiecesDelta {
+    let mut delta = PiecesDelta::default();
+
+    let old: HashMap<u16, &Character> = baseline.iter().map(|p| (p.unique_id, p)).collect();
+    for piece in current {
+        match old.get(&piece.unique_id) {
+            Some(old) => delta.changed.extend(CharacterDelta::between(old, piece)),
+            None => delta.added.push(piece.clone()),
+        }
+    }
+
+    let still_here: HashSet<u16> = current.iter().map(|p| p.unique_id).collect();
+    delta.removed = baseline
+        .iter()
+        .map(|p| p.unique_id)
+        .filter(|id| !still_here.contains(id))
+        .collect();
+
+    delta
+}
+
+pub fn apply(baseline: &[Character], delta: &PiecesDelta) -> Vec<Character> {
+    let mut pieces: Vec<Character> = baseline
+        .iter()
+        .filter(|p| !delta.removed.contains(&p.unique_id))
+        .cloned()
+        .collect();
+
+    let index: HashMap<u16, usize> = pieces
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.unique_id, i))
+        .collect();
+    for change in &delta.changed {
+        if let Some(&i) = index.get(&change.unique_id) {
+            change.apply(&mut pieces[i]);
+        }
+    }
+
+    pieces.extend(delta.added.iter().cloned());
+    pieces
+}
+
+// server side, one per client: remembers what was sent so the next tick can go out as a delta
+#[derive(Default)]
+pub struct DeltaEncoder {
+    history: VecDeque<(u64, Arc<Vec<Character>>)>,
+}
+
+impl DeltaEncoder {
+    pub fn encode(
+        &mut self,
+        acked: Option<u64>,
+        tick: u64,
+        pieces: Arc<Vec<Character>>,
+        terrain: &Terrain,
+    ) -> ServerMessage {
+        // nothing older than the acknowledged snapshot will ever be diffed against again
+        if let Some(acked) = acked {
+            self.history.retain(|(t, _)| *t >= acked);
+        }
+        while self.history.len() >= SNAPSHOT_HISTORY_LEN {
+            self.history.pop_front();
+        }
+
+        let baseline = acked.and_then(|acked| self.history.iter().find(|(t, _)| *t == acked));
+
+        let message = match baseline {
+            Some((baseline, old)) if !tick.is_multiple_of(KEYFRAME_INTERVAL) => {
+                ServerMessage::Delta {
+                    baseline: *baseline,
+                    tick,
+                    delta: diff(old, &pieces),
+                }
+            }
+            // the ground never changes during a battle, so only keyframes carry it
+            _ => ServerMessage::Snapshot {
+                tick,
+                pieces: pieces.to_vec(),
+                terrain: terrain.clone(),
+            },
+        };
+
+        self.history.push_back((tick, pieces));
+        message
+    }
+}
+
+// client side: rebuilds full snapshots from keyframes and the deltas that follow them
+#[derive(Default)]
+pub struct DeltaDecoder {
+    history: VecDeque<(u64, Vec<Character>)>,
+}
+
+impl DeltaDecoder {
+    pub fn keyframe(&mut self, tick: u64, pieces: Vec<Character>) -> &[Character] {
+        self.remember(tick, pieces)
+    }
+
+    // None when the baseline has already been forgotten, the next keyframe will resync
+    pub fn delta(&mut self, baseline: u64, tick: u64, delta: &PiecesDelta) -> Option<&[Character]> {
+        let (_, old) = self.history.iter().find(|(t, _)| *t == baseline)?;
+        let pieces = apply(old, delta);
+        Some(self.remember(tick, pieces))
+    }
+
+    pub fn latest_tick(&self) -> Option<u64> {
+        self.history.back().map(|(t, _)| *t)
+    }
+
+    fn remember(&mut self, tick: u64, pieces: Vec<Character>) -> &[Character] {
+        while self.history.len() >= SNAPSHOT_HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back((tick, pieces));
+        &self.history.back().unwrap().1
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+
+use crate::map::*;
+use crate::terrain::*;
+use crate::utils::calc_line;
+use crate::Team;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    // paints the tile under the cursor
+    Brush,
+    // the first press drops an anchor, the second paints from there to the cursor
+    Line,
+    Rect,
+}
+
+// a map being drawn, the client only turns keys and clicks into calls on this
+pub struct MapEditor {
+    pub map: Map,
+    // where the map is saved, and was loaded from if it was
+    pub path: PathBuf,
+    pub cursor: (i16, i16),
+    pub brush: Tile,
+    pub tool: Tool,
+    pub anchor: Option<(i16, i16)>,
+    // whose spawn points get placed
+    pub team: Team,
+    // changed since it was last loaded or saved
+    pub dirty: bool,
+}
+
+impl MapEditor {
+    pub fn new(map: Map, path: &Path) -> MapEditor {
+        MapEditor {
+            map,
+            path: path.to_path_buf(),
+            cursor: (0, 0),
+            brush: Tile::Wall,
+            tool: Tool::Brush,
+            anchor: None,
+            team: TEAMS[0],
+            dirty: false,
+        }
+    }
+
+    // a file that is not there yet starts as a blank map named after it
+    pub fn open(path: &Path, width: i16, height: i16) -> Result<MapEditor, MapError> {
+        if path.exists() {
+            return Ok(MapEditor::new(Map::load(path)?, path));
+        }
+        let name = path
+            .file_stem()
+            .map_or("untitled".into(), |stem| stem.to_string_lossy());
+        let mut editor = MapEditor::new(Map::blank(&name, width, height), path);
+        editor.dirty = true;
+        Ok(editor)
+    }
+
+    pub fn save(&mut self) -> Result<(), MapError> {
+        self.map.save(&self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    // the cursor stays on the field
+    pub fn move_cursor(&mut self, dx: i16, dy: i16) {
+        self.set_cursor(self.cursor.0 + dx, self.cursor.1 + dy);
+    }
+
+    pub fn set_cursor(&mut self, x: i16, y: i16) {
+        self.cursor = (
+            x.clamp(0, self.map.width - 1),
+            y.clamp(0, self.map.height - 1),
+        );
+    }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+        self.anchor = None;
+    }
+
+    // what pressing paint would do to the map, so the client can show it before it happens
+    pub fn preview(&self) -> Vec<(i16, i16)> {
+        let (x, y) = self.cursor;
+        match (self.tool, self.anchor) {
+            (Tool::Brush, _) | (_, None) => vec![(x, y)],
+            (Tool::Line, Some((ax, ay))) => calc_line(ax as i32, ay as i32, x as i32, y as i32)
+                .into_iter()
+                .map(|(x, y)| (x as i16, y as i16))
+                .collect(),
+            (Tool::Rect, Some((ax, ay))) => {
+                let mut cells = Vec::new();
+                for cy in ay.min(y)..=ay.max(y) {
+                    for cx in ax.min(x)..=ax.max(x) {
+                        cells.push((cx, cy));
+                    }
+                }
+                cells
+            }
+        }
+    }
+
+    // the brush paints straight away, lines and rectangles wait for their second corner
+    pub fn paint(&mut self) {
+        if self.tool != Tool::Brush && self.anchor.is_none() {
+            self.anchor = Some(self.cursor);
+            return;
+        }
+        for (x, y) in self.preview() {
+            self.map.tiles.set(x, y, self.brush);
+        }
+        self.anchor = None;
+        self.dirty = true;
+    }
+
+    // the tile under the cursor becomes the brush
+    pub fn pick(&mut self) {
+        self.brush = self.map.tiles.tile(self.cursor.0, self.cursor.1);
+    }
+
+    pub fn switch_team(&mut self) {
+        let next = TEAMS
+            .iter()
+            .position(|&t| t == self.team)
+            .map_or(0, |i| i + 1);
+        self.team = TEAMS[next % TEAMS.len()];
+    }
+
+    // adds the cursor to the team's spawn points as the next key, or takes it away if it is one;
+    // false if there is no room for another or the cell cannot be spawned on
+    pub fn toggle_spawn(&mut self) -> bool {
+        let cursor = self.cursor;
+        let taken = self
+            .map
+            .spawns
+            .iter()
+            .any(|zone| zone.team != self.team && zone.points.contains(&cursor));
+        let open = self.map.tiles.passable(cursor.0, cursor.1);
+
+        let zone = match self
+            .map
+            .spawns
+            .iter_mut()
+            .find(|zone| zone.team == self.team)
+        {
+            Some(zone) => zone,
+            None => {
+                self.map.spawns.push(SpawnZone {
+                    team: self.team,
+                    points: Vec::new(),
+                });
+                self.map.spawns.last_mut().unwrap()
+            }
+        };
+
+        if let Some(i) = zone.points.iter().position(|&p| p == cursor) {
+            zone.points.remove(i);
+        } else if zone.points.len() < MAX_SPAWN_POINTS && open && !taken {
+            zone.points.push(cursor);
+        } else {
+            return false;
+        }
+        self.dirty = true;
+        true
+    }
+
+    // copies the half of the map the cursor is on over the other half, and the spawn points of
+    // the team being placed over to the other side, so the sides start out even
+    pub fn mirror(&mut self) {
+        let (width, height) = (self.map.width, self.map.height);
+        let left = self.cursor.0 < width / 2;
+        for y in 0..height {
+            for x in 0..width / 2 {
+                let (from, to) = if left {
+                    (x, width - 1 - x)
+                } else {
+                    (width - 1 - x, x)
+                };
+                let tile = self.map.tiles.tile(from, y);
+                self.map.tiles.set(to, y, tile);
+            }
+        }
+
+        let across: Vec<(i16, i16)> = self
+            .map
+            .spawn_points(self.team)
+            .iter()
+            .map(|&(x, y)| (width - 1 - x, y))
+            .collect();
+        for zone in self.map.spawns.iter_mut().filter(|z| z.team != self.team) {
+            zone.points = across.clone();
+        }
+        self.dirty = true;
+    }
+}
@@ -0,0 +1,296 @@
+use std::{collections::VecDeque, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+// who an event is about, enough to tell them apart without a copy of the field
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fighter {
+    pub unique_id: u16,
+    pub denotation: char,
+    pub team: Team,
+}
+
+impl Fighter {
+    pub fn of(piece: &Character) -> Fighter {
+        Fighter {
+            unique_id: piece.unique_id,
+            denotation: piece.denotation,
+            team: piece.team,
+        }
+    }
+}
+
+impl fmt::Display for Fighter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{:x}", self.denotation, self.unique_id)
+    }
+}
+
+// everything that happens on the field, in the order the world does it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CombatEvent {
+    Spawned {
+        unit: Fighter,
+        at: (i16, i16),
+        hp: i16,
+    },
+    Moved {
+        unit: Fighter,
+        from: (i16, i16),
+        to: (i16, i16),
+    },
+    AttackStarted {
+        attacker: Fighter,
+        target: Fighter,
+    },
+    // 2d6 plus the attacker's skill, against the target's defence with any cover it has
+    Rolled {
+        attacker: Fighter,
+        target: Fighter,
+        roll: i16,
+        defence: i16,
+    },
+    Hit {
+        attacker: Fighter,
+        target: Fighter,
+        damage: i16,
+        hp_left: i16,
+    },
+    Miss {
+        attacker: Fighter,
+        target: Fighter,
+    },
+    // always straight after the hit that did it
+    Killed {
+        attacker: Fighter,
+        target: Fighter,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventKind {
+    Spawned,
+    Moved,
+    AttackStarted,
+    Rolled,
+    Hit,
+    Miss,
+    Killed,
+}
+
+impl CombatEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            CombatEvent::Spawned { .. } => EventKind::Spawned,
+            CombatEvent::Moved { .. } => EventKind::Moved,
+            CombatEvent::AttackStarted { .. } => EventKind::AttackStarted,
+            CombatEvent::Rolled { .. } => EventKind::Rolled,
+            CombatEvent::Hit { .. } => EventKind::Hit,
+            CombatEvent::Miss { .. } => EventKind::Miss,
+            CombatEvent::Killed { .. } => EventKind::Killed,
+        }
+    }
+
+    // the one doing it, the unit itself for spawns and moves
+    pub fn actor(&self) -> Fighter {
+        match self {
+            CombatEvent::Spawned { unit, .. } | CombatEvent::Moved { unit, .. } => *unit,
+            CombatEvent::AttackStarted { attacker, .. }
+            | CombatEvent::Rolled { attacker, .. }
+            | CombatEvent::Hit { attacker, .. }
+            | CombatEvent::Miss { attacker, .. }
+            | CombatEvent::Killed { attacker, .. } => *attacker,
+        }
+    }
+
+    // the one it is done to, if anyone
+    pub fn target(&self) -> Option<Fighter> {
+        match self {
+            CombatEvent::Spawned { .. } | CombatEvent::Moved { .. } => None,
+            CombatEvent::AttackStarted { target, .. }
+            | CombatEvent::Rolled { target, .. }
+            | CombatEvent::Hit { target, .. }
+            | CombatEvent::Miss { target, .. }
+            | CombatEvent::Killed { target, .. } => Some(*target),
+        }
+    }
+}
+
+impl fmt::Display for CombatEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CombatEvent::Spawned { unit, at, hp } => {
+                write!(f, "{unit} joins at {},{} with {hp} hp", at.0, at.1)
+            }
+            CombatEvent::Moved { unit, to, .. } => write!(f, "{unit} moves to {},{}", to.0, to.1),
+            CombatEvent::AttackStarted { attacker, target } => {
+                write!(f, "{attacker} will attack {target}")
+            }
+            CombatEvent::Rolled {
+                attacker,
+                roll,
+                defence,
+                ..
+            } => write!(
+                f,
+                "{attacker} rolled to attack: {roll} vs enemy defence: {defence}"
+            ),
+            CombatEvent::Hit {
+                attacker,
+                target,
+                damage,
+                hp_left,
+            } => write!(
+                f,
+                "{attacker} causes {damage} damage to {target}, leaving {hp_left} hp"
+            ),
+            CombatEvent::Miss { attacker, target } => write!(f, "{attacker} misses {target}"),
+            CombatEvent::Killed { attacker, target } => write!(f, "{attacker} defeated {target}"),
+        }
+    }
+}
+
+// anything following a battle as it happens, fed each tick's events in one go
+pub trait EventSink {
+    fn record(&mut self, tick: u64, events: &[CombatEvent]);
+}
+
+// readable lines for a log file, everything but the moves
+#[derive(Default)]
+pub struct TextLog {
+    lines: Vec<String>,
+}
+
+impl TextLog {
+    // the lines written since the last call, oldest first
+    pub fn take(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.lines)
+    }
+}
+
+impl EventSink for TextLog {
+    fn record(&mut self, tick: u64, events: &[CombatEvent]) {
+        self.lines.extend(
+            events
+                .iter()
+                .filter(|e| e.kind() != EventKind::Moved)
+                .map(|e| format!("{tick:>6}  {e}")),
+        );
+    }
+}
+
+// which lines a combat log shows, everything when nothing is set
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LogFilter {
+    // only what this side does
+    pub team: Option<Team>,
+    // only events this unit takes part in, on either side, by its denotation
+    pub unit: Option<char>,
+    pub kind: Option<EventKind>,
+}
+
+impl LogFilter {
+    pub fn matches(&self, event: &CombatEvent) -> bool {
+        let involved = |denotation| {
+            event.actor().denotation == denotation
+                || event.target().map(|t| t.denotation) == Some(denotation)
+        };
+        self.team.is_none_or(|team| event.actor().team == team)
+            && self.unit.is_none_or(involved)
+            && self.kind.is_none_or(|kind| event.kind() == kind)
+    }
+}
+
+// the current battle's events for a scrollable pane, the oldest dropped once it is full
+pub struct CombatLog {
+    entries: VecDeque<(u64, CombatEvent)>,
+    capacity: usize,
+    filter: LogFilter,
+    // how many matching lines back from the newest the view is
+    scroll: usize,
+}
+
+impl CombatLog {
+    pub fn new(capacity: usize) -> CombatLog {
+        CombatLog {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+            filter: LogFilter::default(),
+            scroll: 0,
+        }
+    }
+
+    // a new battle starts with an empty log, the filter stays as it was
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.scroll = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn filter(&self) -> LogFilter {
+        self.filter
+    }
+
+    // the view jumps back to the newest lines, the old scroll position means nothing under a new filter
+    pub fn set_filter(&mut self, filter: LogFilter) {
+        self.filter = filter;
+        self.scroll = 0;
+    }
+
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    pub fn matching(&self) -> impl Iterator<Item = &(u64, CombatEvent)> {
+        self.entries.iter().filter(|(_, e)| self.filter.matches(e))
+    }
+
+    // the lines a pane this tall shows, oldest first
+    pub fn page(&self, height: usize) -> Vec<&(u64, CombatEvent)> {
+        let matching: Vec<_> = self.matching().collect();
+        let end = matching.len() - self.scroll.min(matching.len());
+        matching[end.saturating_sub(height)..end].to_vec()
+    }
+
+    // back through older lines, never so far that the pane is left part empty
+    pub fn page_up(&mut self, height: usize) {
+        let furthest = self.matching().count().saturating_sub(height);
+        self.scroll = (self.scroll + height).min(furthest);
+    }
+
+    pub fn page_down(&mut self, height: usize) {
+        self.scroll = self.scroll.saturating_sub(height);
+    }
+
+    pub fn scroll_to_end(&mut self) {
+        self.scroll = 0;
+    }
+}
+
+impl EventSink for CombatLog {
+    fn record(&mut self, tick: u64, events: &[CombatEvent]) {
+        for event in events.iter().filter(|e| e.kind() != EventKind::Moved) {
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+            // keep the view still while reading back through older lines
+            if self.scroll > 0 && self.filter.matches(event) {
+                self.scroll += 1;
+            }
+            self.entries.push_back((tick, event.clone()));
+        }
+        // lines dropped off the front can leave nothing that far back
+        if self.scroll > 0 {
+            self.scroll = self.scroll.min(self.matching().count().saturating_sub(1));
+        }
+    }
+}
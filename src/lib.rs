@@ -1,17 +1,61 @@
 use std::ops::Range;
 
-use crossterm::style::Color;
-use serde::{Serialize, Deserialize};
+use serde::{Deserialize, Serialize};
 
+pub mod ai;
+pub mod balance;
+pub mod catalogue;
+pub mod delta;
+pub mod editor;
+pub mod events;
+pub mod map;
+pub mod net;
+pub mod pathfinding;
+pub mod replay;
+pub mod server;
+pub mod spatial;
+pub mod terrain;
+pub mod timestep;
+pub mod utils;
+pub mod world;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+// the two sides of a battle, written "green" and "red" in map and replay files
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "&'static str", try_from = "String")]
+pub enum Team {
+    Green,
+    Red,
+}
+
+impl From<Team> for &'static str {
+    fn from(team: Team) -> &'static str {
+        match team {
+            Team::Green => "green",
+            Team::Red => "red",
+        }
+    }
+}
+
+impl TryFrom<String> for Team {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Team, String> {
+        match name.as_str() {
+            "green" => Ok(Team::Green),
+            "red" => Ok(Team::Red),
+            _ => Err(format!("there is no {name} team, only green and red")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Character {
     pub unique_id: u16,
 
     pub x: i16,
     pub y: i16,
     pub denotation: char,
-    pub color: Color,
+    pub team: Team,
     pub hp: i16,
     pub attack_skill: i16,
     pub damage_range: Range<i16>,
@@ -34,4 +78,124 @@ pub enum CommandState {
     MainGame,
     CharacterSelected(char),
     Chat,
+    // drawing a map file, no server involved
+    MapEditor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchPhase {
+    // players place units and ready up while the battle stays frozen
+    Lobby,
+    // everyone is ready, the battle starts when this reaches zero
+    Countdown { seconds_left: u32 },
+    Running,
+    // the battle is over until someone asks for a rematch
+    Finished(world::Outcome),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ClientMessage {
+    // the name of one of the server's maps, None for its default
+    NewGame { map: Option<String> },
+    JoinGame { code: String },
+    RequestSnapshot,
+    // the newest snapshot the client has rebuilt, future deltas are taken against it
+    Acknowledge { tick: u64 },
+    // unit is a name from the catalogue the server sent when you joined, it fights for your team
+    SpawnUnit { unit: String, row: u8 },
+    // in the lobby, the countdown starts once every player in the session is ready
+    Ready,
+    // once the match is finished, clears the field and takes everyone back to the lobby
+    Rematch,
+    // shown next to your chat lines, until then the server calls you by your player number
+    SetName { name: String },
+    Chat { text: String },
+}
+
+// longer names and chat lines are cut short by the server
+pub const MAX_NAME_LEN: usize = 16;
+pub const MAX_CHAT_LEN: usize = 200;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    // the server decides who you are and which side you play for, the client just follows along
+    // the catalogue lists the units this server lets you field, the map is where the session fights
+    // the rng seed stays with the server and the replay, anyone holding it would know every roll in advance
+    GameCreated {
+        code: String,
+        player: u32,
+        team: Team,
+        phase: MatchPhase,
+        catalogue: catalogue::Catalogue,
+        map: map::Map,
+    },
+    GameJoined {
+        code: String,
+        player: u32,
+        team: Team,
+        phase: MatchPhase,
+        catalogue: catalogue::Catalogue,
+        map: map::Map,
+    },
+    // pushed to everyone in the session whenever the match moves on
+    Phase(MatchPhase),
+    Snapshot {
+        tick: u64,
+        pieces: Vec<Character>,
+        terrain: terrain::Terrain,
+    },
+    Delta {
+        baseline: u64,
+        tick: u64,
+        delta: delta::PiecesDelta,
+    },
+    // everything but the moves that happened on the field since the last lot, tick is the battle's own
+    Events {
+        tick: u64,
+        events: Vec<events::CombatEvent>,
+    },
+    SpawnAccepted,
+    // your gold, pushed whenever it changes, and what the battle pays you a second while it runs
+    Gold {
+        gold: u32,
+        income: u32,
+    },
+    // a chat line from someone in your session, your own lines come back this way too
+    Chat {
+        player: u32,
+        name: String,
+        team: Team,
+        text: String,
+    },
+    Error(ServerError),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ServerError {
+    // the request could not be decoded as a ClientMessage
+    Malformed(String),
+    // no session exists with the code the client tried to join
+    UnknownSession(String),
+    // the request only makes sense once the client has created or joined a session
+    NotInSession,
+    // a connection belongs to one session for its lifetime, open another to play elsewhere
+    AlreadyInSession,
+    // the unit is not in this server's catalogue
+    UnknownUnit(String),
+    // spawns have to land on one of the map's spawn points for the team
+    RowOutOfRange(u8),
+    // the unit costs more than you have, these are the two amounts
+    NotEnoughGold {
+        cost: u32,
+        gold: u32,
+    },
+    // every unit id is in use, nothing more comes on until some fall
+    FieldFull,
+    // the server has no map by that name, these are the ones it does have
+    UnknownMap {
+        name: String,
+        available: Vec<String>,
+    },
+    // the request does not fit the phase the match is in right now
+    WrongPhase(MatchPhase),
 }
@@ -1,6 +1,8 @@
 use std::{
+    collections::VecDeque,
     fmt::Display,
     io::{stdout, Write},
+    path::{Path, PathBuf},
     time::Duration,
 };
 
@@ -8,80 +10,252 @@ use async_std::{
     net::{TcpListener, TcpStream},
     task::{block_on, spawn},
 };
-use futures::{future::FutureExt, pin_mut, select, AsyncReadExt, AsyncWriteExt, StreamExt};
+use futures::{
+    channel::mpsc::{unbounded, TryRecvError, UnboundedReceiver},
+    future::{Future, FutureExt},
+    select, AsyncWriteExt, StreamExt,
+};
 use futures_timer::Delay;
 
 use crossterm::{
     cursor::{Hide, MoveLeft, MoveTo, Show},
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyCode, KeyEvent},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyCode, KeyEvent,
+        MouseButton, MouseEvent, MouseEventKind,
+    },
     execute, queue,
     style::{Color, Print},
     terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
     Result,
 };
 
-mod utils;
-use utils::*;
-mod lib;
-use lib::*;
-mod server;
-use server::*;
+use fracas::{
+    ai::*, catalogue::*, delta::*, editor::*, events::*, map::*, net::*, replay::*, server::*,
+    terrain::*, utils::*, world::Outcome, *,
+};
+
+// the lines of text under the field are padded out to this so a shorter one clears the last
+const LINE_WIDTH: usize = 72;
+
+// the chat pane sits to the right of the grid, newest line at the bottom
+const CHAT_PANE_LINES: usize = 8;
+const CHAT_PANE_WIDTH: usize = 50;
+const CHAT_HISTORY_LEN: usize = 200;
 
-async fn events(listening_port: u16) {
+// the combat log runs down the right of the field under the chat, holding this many events of the current battle
+const COMBAT_PANE_LINES: usize = 12;
+const COMBAT_PANE_WIDTH: usize = 50;
+const COMBAT_HISTORY_LEN: usize = 5_000;
+
+struct ChatLine {
+    name: String,
+    team: Team,
+    text: String,
+}
+
+async fn events(server_address: String, autostart: Option<ClientMessage>, name: Option<String>) {
     let mut pieces: Vec<Character> = Vec::new();
     let mut command_state: CommandState = CommandState::Menu;
     let mut reader = EventStream::new();
 
-    let mut game_session_code: String;
-    let mut connection_address: String = String::new();
-
-    let mut piece_colour: Color = Color::Green;
-
-    print_at(20, 0, format!("Port: {}", listening_port));
+    let mut connection: Option<TcpStream> = None;
+    let mut incoming: Option<UnboundedReceiver<ServerMessage>> = None;
+    let mut decoder = DeltaDecoder::default();
+
+    let mut piece_team = Team::Green;
+    let mut phase = MatchPhase::Lobby;
+    // whatever the server says can be fielded, it arrives with the game code
+    let mut catalogue = Catalogue::default();
+    // the server keeps the books, this is only its last word on them: gold and income a second
+    let mut gold: Option<(u32, u32)> = None;
+    // why the last spawn was turned down, until the next one goes through
+    let mut spawn_notice = String::new();
+    // the session's map arrives with the game code, the terrain on it with every keyframe
+    let mut map = Map::built_in().clone();
+    let mut terrain = map.tiles.clone();
+
+    let mut chat_lines: VecDeque<ChatLine> = VecDeque::new();
+    let mut chat_input = String::new();
+    // how many lines back from the newest the pane is scrolled
+    let mut chat_scroll: usize = 0;
+
+    let mut combat_log = CombatLog::new(COMBAT_HISTORY_LEN);
+
+    // picked from the menu, the computer joins as soon as the server says which session to join
+    let mut computer: Option<Difficulty> = None;
+    // the computer plays on its own task, all it ever sends back is why it stopped
+    let (computer_stopped, mut computer_failures) = unbounded::<String>();
+    let mut computer_notice = String::new();
+
+    // only while drawing a map, along with the last thing it had to say
+    let mut map_editor: Option<MapEditor> = None;
+    let mut editor_status = String::new();
+    let mut show_asymmetries = false;
+
+    print_at(20, 0, format!("Server: {}", server_address));
+
+    // started with --connect, skip the menu and go straight into the session
+    if let Some(first) = autostart {
+        if let Some((stream, messages)) =
+            open_session(&server_address, &first, name.as_deref()).await
+        {
+            connection = Some(stream);
+            incoming = Some(messages);
+            command_state = CommandState::MainGame;
+        }
+    }
 
     loop {
-        let mut delay = Delay::new(Duration::from_millis(1_00)).fuse();
+        let mut delay = Delay::new(Duration::from_millis(100)).fuse();
         let mut term_event = reader.next().fuse();
 
         select! {
             _ = delay => {
                 // updates every tick of delay
-                if command_state != CommandState::Menu {
-
-                    if connection_address.len() > 0 {
-                        let raw = callb(b"update", &connection_address).await;
-                        pieces = match bincode::deserialize(&raw) {
-                            Ok(f) => f,
-                            Err(e) => {
-                                logging(format!("Error deserializing : {:?}", e)).await;
-                                continue;
-                            },
-                        };
+                if command_state == CommandState::MapEditor {
+                    if let Some(editor) = &map_editor {
+                        render_editor(5, 4, editor, show_asymmetries, &editor_status);
+                    }
+                } else if command_state != CommandState::Menu {
+
+                    // drain everything the server pushed since the last frame, only the newest snapshot matters
+                    if let Some(messages) = incoming.as_mut() {
+                        loop {
+                            match messages.try_recv() {
+                                Ok(ServerMessage::Snapshot { tick, pieces: p, terrain: t }) => {
+                                    pieces = decoder.keyframe(tick, p).to_vec();
+                                    terrain = t;
+                                },
+                                Ok(ServerMessage::Delta { baseline, tick, delta }) => {
+                                    if let Some(p) = decoder.delta(baseline, tick, &delta) {
+                                        pieces = p.to_vec();
+                                    }
+                                },
+                                Ok(ServerMessage::GameCreated { code, team, phase: p, catalogue: c, map: m, .. }) | Ok(ServerMessage::GameJoined { code, team, phase: p, catalogue: c, map: m, .. }) => {
+                                    piece_team = team;
+                                    phase = p;
+                                    catalogue = c;
+                                    combat_log.clear();
+                                    gold = None;
+                                    // a different sized field leaves the old layout behind
+                                    if (m.width, m.height) != (map.width, map.height) {
+                                        cls();
+                                    }
+                                    map = m;
+                                    terrain = map.tiles.clone();
+                                    print_at(50, 0, format!("Game Code: {code}  Map: {}", map.name));
+                                    if let Some(difficulty) = computer.take() {
+                                        let stopped = computer_stopped.clone();
+                                        let game = play(server_address.clone(), code, difficulty);
+                                        spawn(async move {
+                                            let _ = stopped.unbounded_send(game.await);
+                                        });
+                                        computer_notice.clear();
+                                    }
+                                },
+                                Ok(ServerMessage::Events { tick, events }) => combat_log.record(tick, &events),
+                                Ok(ServerMessage::SpawnAccepted) => spawn_notice.clear(),
+                                Ok(ServerMessage::Gold { gold: g, income }) => gold = Some((g, income)),
+                                Ok(ServerMessage::Error(ServerError::NotEnoughGold { cost, gold })) => {
+                                    spawn_notice = format!("That costs {cost} gold, you have {gold}");
+                                },
+                                Ok(ServerMessage::Error(ServerError::FieldFull)) => {
+                                    spawn_notice = "The field is full, wait for some to fall".to_string();
+                                },
+                                Ok(ServerMessage::Phase(p)) => {
+                                    // a rematch is a new battle, with a log of its own
+                                    if p == MatchPhase::Lobby && phase != MatchPhase::Lobby {
+                                        combat_log.clear();
+                                    }
+                                    phase = p;
+                                },
+                                Ok(ServerMessage::Chat { name, team, text, .. }) => {
+                                    if chat_lines.len() >= CHAT_HISTORY_LEN {
+                                        chat_lines.pop_front();
+                                    }
+                                    chat_lines.push_back(ChatLine { name, team, text });
+                                    if chat_scroll > 0 {
+                                        chat_scroll = (chat_scroll + 1).min(chat_lines.len().saturating_sub(CHAT_PANE_LINES));
+                                    }
+                                },
+                                Ok(ServerMessage::Error(ServerError::UnknownSession(code))) => {
+                                    print_at(50, 0, format!("No game with code: {code}"));
+                                    incoming = None;
+                                    connection = None;
+                                    command_state = CommandState::Menu;
+                                    break;
+                                },
+                                Ok(ServerMessage::Error(ServerError::UnknownMap { name, available })) => {
+                                    print_at(50, 0, format!("No map called {name}, try: {}", available.join(", ")));
+                                    computer = None;
+                                    incoming = None;
+                                    connection = None;
+                                    command_state = CommandState::Menu;
+                                    break;
+                                },
+                                Ok(ServerMessage::Error(e)) => {
+                                    logging(format!("Server error : {:?}", e)).await;
+                                },
+                                Err(TryRecvError::Closed) => {
+                                    print_at(35, 0, "!Network 📶!");
+                                    incoming = None;
+                                    connection = None;
+                                    break;
+                                },
+                                Err(TryRecvError::Empty) => break,
+                            }
+                        }
+
+                        if let Some(tick) = decoder.latest_tick() {
+                            send(&mut connection, &ClientMessage::Acknowledge { tick }).await;
+                        }
                     }
 
                     print_at(10 + (now() % 60) as u16, 2, format!(" Now: {:?} ", now() ));
-                    render_grid(5, 4, &command_state);
+                    print_at(0, 0, format!("{:?}         ", command_state));
+                    render_grid(5, 4, &terrain);
+                    render_map_marks(5, 4, &map, piece_team);
                     print_at(1, 1,
                         format!(
-                            "You are {:?}  |  Green {}    Red {}   ",
-                            piece_colour,
+                            "You are {:?}  |  Green {}    Red {}  |  {:<22}|  {:<50}",
+                            piece_team,
                             pieces
                                 .iter()
-                                .filter(|x| x.hp > 0 && x.color == Color::Green)
+                                .filter(|x| x.hp > 0 && x.team == Team::Green)
                                 .count(),
                             pieces
                                 .iter()
-                                .filter(|x| x.hp > 0 && x.color == Color::Red)
-                                .count()
+                                .filter(|x| x.hp > 0 && x.team == Team::Red)
+                                .count(),
+                            phase_status(&phase),
+                            gold_status(gold, &catalogue)
                         ));
                     render_grid_pieces(5, 4, &pieces);
+
+                    if let MatchPhase::Finished(outcome) = phase {
+                        render_outcome(5, 4, &terrain, outcome);
+                    }
+
+                    // the units hang below the field and the panes sit beside it, however big the map makes it
+                    let below = 4 + terrain.height() as u16;
+                    render_units(4, below + 2, &catalogue);
+                    print_at(4, below + 3, format!("{:<width$}", spawn_notice, width = LINE_WIDTH));
+                    if let Ok(why) = computer_failures.try_recv() {
+                        computer_notice = why;
+                    }
+                    print_at(4, below + 4, format!("{:<width$}", computer_notice, width = LINE_WIDTH));
+
+                    let beside = 5 + terrain.width() as u16 + 5;
+                    let typing = if command_state == CommandState::Chat { Some(chat_input.as_str()) } else { None };
+                    render_chat(beside, 4, &chat_lines, chat_scroll, typing);
+                    render_combat_log(beside, 6 + CHAT_PANE_LINES as u16, &combat_log, &catalogue);
                 }
 
-                logging_tail().await;
                 stdout().flush().unwrap();
             },
             term_handler = term_event => {
                 let mut key_code : KeyCode = KeyCode::Null;
+                let mut mouse : Option<MouseEvent> = None;
 
                 match term_handler {
                     Some(Ok(evt)) => {
@@ -90,12 +264,11 @@ async fn events(listening_port: u16) {
                             Event::Key(key) => {
                                 key_code = key.code
                             },
-                            //Event::Mouse(_) => (),
+                            Event::Mouse(m) => mouse = Some(m),
                             Event::Resize(w, h) => {
                                 cls();
                                 print_at(50, 2, format!("Terminal Size : {w}x{h}"));
                             },
-                            _ => ()
                         }
                     }
                     Some(Err(e)) => println!("Error: {:?}\r", e),
@@ -106,54 +279,126 @@ async fn events(listening_port: u16) {
                     CommandState::Menu => {
                         match key_code {
                             KeyCode::Char('h') => {
-                                piece_colour = Color::Green;
-                                connection_address = format!("localhost:{listening_port}");
+                                let map = ask_map();
+                                if let Some((stream, messages)) = open_session(&server_address, &ClientMessage::NewGame { map }, name.as_deref()).await {
+                                    connection = Some(stream);
+                                    incoming = Some(messages);
+                                    decoder = DeltaDecoder::default();
 
-                                game_session_code = call(b"new game", &connection_address).await;
-                                print_at(50, 0, format!("Game Code: {game_session_code}"));
-
-                                command_state = CommandState::MainGame;
+                                    command_state = CommandState::MainGame;
+                                }
                             },
                             KeyCode::Char('c') => {
                                 print_at(20, 1, "Connect to server-address:port : ".to_string());
-                                connection_address = match read_line("localhost:") {
+                                let connection_address = match read_line("localhost:") {
                                     Ok(n) => n,
                                     Err(_) => "".to_string()
                                 };
                                 print_at(20, 1, " ".repeat(50));
 
-                                if connection_address.len() > 0 {
-                                    piece_colour = Color::Red;
+                                print_at(20, 1, "Game Code : ".to_string());
+                                let code = match read_line("") {
+                                    Ok(n) => n.trim().to_string(),
+                                    Err(_) => "".to_string()
+                                };
+                                print_at(20, 1, " ".repeat(50));
 
-                                    game_session_code = call(b"new game", &connection_address).await;
-                                    print_at(50, 0, format!("Game Code: {game_session_code}"));
+                                if !connection_address.is_empty() && !code.is_empty() {
+                                    if let Some((stream, messages)) = open_session(&connection_address, &ClientMessage::JoinGame { code }, name.as_deref()).await {
+                                        connection = Some(stream);
+                                        incoming = Some(messages);
+                                        decoder = DeltaDecoder::default();
 
-                                    command_state = CommandState::MainGame;
+                                        command_state = CommandState::MainGame;
+                                    }
                                 }
                             },
-                            KeyCode::Char('t') => { command_state = CommandState::Chat; }
+                            // a game of your own with the computer on the other side
+                            KeyCode::Char('a') => {
+                                print_at(20, 1, "Computer (1 random, 2 counter, 3 economy) : ".to_string());
+                                let difficulty = match read_line("") {
+                                    Ok(n) => n.trim().parse::<usize>().ok().and_then(|n| Difficulty::ALL.get(n.wrapping_sub(1)).copied()),
+                                    Err(_) => None
+                                };
+                                print_at(20, 1, " ".repeat(50));
+
+                                if let Some(difficulty) = difficulty {
+                                    let map = ask_map();
+                                    if let Some((stream, messages)) = open_session(&server_address, &ClientMessage::NewGame { map }, name.as_deref()).await {
+                                        connection = Some(stream);
+                                        incoming = Some(messages);
+                                        decoder = DeltaDecoder::default();
+                                        computer = Some(difficulty);
+
+                                        command_state = CommandState::MainGame;
+                                    }
+                                }
+                            },
+                            KeyCode::Char('e') => {
+                                print_at(20, 1, "Map file to edit : ".to_string());
+                                let path = match read_line("") {
+                                    Ok(n) => PathBuf::from(n.trim()),
+                                    Err(_) => PathBuf::new()
+                                };
+                                print_at(20, 1, " ".repeat(50));
+
+                                if let Some(editor) = open_editor(&path) {
+                                    map_editor = Some(editor);
+                                    editor_status = "Editing, w to save".to_string();
+                                    show_asymmetries = false;
+                                    cls();
+                                    command_state = CommandState::MapEditor;
+                                }
+                            },
+                            // chat goes to the people in your session, so there has to be one
+                            KeyCode::Char('t') if connection.is_some() => { command_state = CommandState::Chat; }
                             KeyCode::Char('q') => break,
                             _ => (),
                         }
 
                     },
                     CommandState::MainGame => {
-                        if let KeyCode::Char(c) = key_code {
-                            print_at(40,0,format!("Char: {}", c));
-                            match c {
-                                'q' => command_state = CommandState::Menu,
-                                _ => command_state = CommandState::CharacterSelected(c)
-                            }
+                        // the combat log is driven from keys no unit can be given
+                        let mut filter = combat_log.filter();
+                        match key_code {
+                            KeyCode::PageUp => combat_log.page_up(COMBAT_PANE_LINES),
+                            KeyCode::PageDown => combat_log.page_down(COMBAT_PANE_LINES),
+                            KeyCode::End => combat_log.scroll_to_end(),
+                            KeyCode::F(1) => {
+                                filter.team = cycle(filter.team, &[Team::Green, Team::Red]);
+                                combat_log.set_filter(filter);
+                            },
+                            KeyCode::F(2) => {
+                                let units: Vec<char> = catalogue.units.iter().map(|u| u.denotation).collect();
+                                filter.unit = cycle(filter.unit, &units);
+                                combat_log.set_filter(filter);
+                            },
+                            KeyCode::F(3) => {
+                                filter.kind = cycle(filter.kind, &[EventKind::Hit, EventKind::Killed, EventKind::Miss]);
+                                combat_log.set_filter(filter);
+                            },
+                            KeyCode::Char(c) => {
+                                print_at(40,0,format!("Char: {}", c));
+                                match c {
+                                    'q' => command_state = CommandState::Menu,
+                                    't' => command_state = CommandState::Chat,
+                                    'r' => {
+                                        match phase {
+                                            MatchPhase::Lobby => send(&mut connection, &ClientMessage::Ready).await,
+                                            MatchPhase::Finished(_) => send(&mut connection, &ClientMessage::Rematch).await,
+                                            _ => (),
+                                        }
+                                    },
+                                    _ => command_state = CommandState::CharacterSelected(c)
+                                }
+                            },
+                            _ => (),
                         }
                     },
                     CommandState::CharacterSelected(c) => {
-                        let col = if piece_colour == Color::Green { 'g' } else { 'r' };
-
-                        let mut character = match c {
-                            'b' => generate_barbarian(0, piece_colour),
-                            'a' => generate_archer(0, piece_colour),
-                            'g' => generate_giant(0, piece_colour),
-                            _ => {
+                        let unit = match catalogue.by_key(c) {
+                            Some(unit) => unit.name.clone(),
+                            None => {
                                 // invalid entry, back out to main game
                                 command_state = CommandState::MainGame;
                                 continue
@@ -161,24 +406,129 @@ async fn events(listening_port: u16) {
                         };
 
                         if let KeyCode::Char(r) = key_code {
-                            if ('1'..='9').contains(&r) {
-                                let r_val : u32 = r as u32;
-                                character.y = ((r_val - 48) * 2) as i16;
-                                // pieces.push(character);
-
-                                let code = format!("{col}{c}{r}");
-                                let code = code.as_bytes();
-                                call(&code, &connection_address).await;
+                            if let Some(row) = r.to_digit(10).map(|row| row as u8).filter(|row| map.spawn_point(piece_team, *row).is_some()) {
+                                let spawn = ClientMessage::SpawnUnit { unit, row };
+                                send(&mut connection, &spawn).await;
                                 command_state = CommandState::MainGame;
                             }
-                        } else {
-                            match key_code {
-                                KeyCode::Esc => { command_state = CommandState::MainGame; },
-                                _ => (),
+                        } else if key_code == KeyCode::Esc {
+                            command_state = CommandState::MainGame;
+                        }
+                    },
+                    CommandState::Chat => {
+                        match key_code {
+                            KeyCode::Enter => {
+                                let text = chat_input.trim().to_string();
+                                if !text.is_empty() {
+                                    send(&mut connection, &ClientMessage::Chat { text }).await;
+                                }
+                                chat_input.clear();
+                                chat_scroll = 0;
+                                command_state = CommandState::MainGame;
+                            },
+                            KeyCode::Esc => {
+                                chat_input.clear();
+                                command_state = CommandState::MainGame;
+                            },
+                            KeyCode::Backspace => { chat_input.pop(); },
+                            KeyCode::Up => {
+                                chat_scroll = (chat_scroll + 1).min(chat_lines.len().saturating_sub(CHAT_PANE_LINES));
+                            },
+                            KeyCode::Down => { chat_scroll = chat_scroll.saturating_sub(1); },
+                            KeyCode::Char(c) if chat_input.chars().count() < MAX_CHAT_LEN => chat_input.push(c),
+                            _ => (),
+                        }
+                    },
+                    CommandState::MapEditor => {
+                        let editor = match map_editor.as_mut() {
+                            Some(editor) => editor,
+                            None => {
+                                command_state = CommandState::Menu;
+                                continue
+                            },
+                        };
+
+                        // the mouse points at cells, the left button paints and the right one picks
+                        if let Some(MouseEvent { kind, column, row, .. }) = mouse {
+                            let (x, y) = (column as i16 - 5, row as i16 - 4);
+                            if editor.map.tiles.contains(x, y) {
+                                editor.set_cursor(x, y);
+                                match kind {
+                                    MouseEventKind::Down(MouseButton::Left) => editor.paint(),
+                                    MouseEventKind::Drag(MouseButton::Left) if editor.tool == Tool::Brush => editor.paint(),
+                                    MouseEventKind::Down(MouseButton::Right) => editor.pick(),
+                                    _ => (),
+                                }
                             }
                         }
+
+                        // anything but a second q in a row changes its mind about leaving
+                        let leaving = editor_status.starts_with("Unsaved");
+                        match key_code {
+                            KeyCode::Up => editor.move_cursor(0, -1),
+                            KeyCode::Down => editor.move_cursor(0, 1),
+                            KeyCode::Left => editor.move_cursor(-1, 0),
+                            KeyCode::Right => editor.move_cursor(1, 0),
+                            KeyCode::Char(' ') | KeyCode::Enter => editor.paint(),
+                            KeyCode::Esc => editor.anchor = None,
+                            KeyCode::Char(c @ '1'..='5') => {
+                                editor.brush = Tile::CODES[c.to_digit(10).unwrap() as usize - 1].0;
+                            },
+                            KeyCode::Char('b') => editor.set_tool(Tool::Brush),
+                            KeyCode::Char('l') => editor.set_tool(Tool::Line),
+                            KeyCode::Char('r') => editor.set_tool(Tool::Rect),
+                            KeyCode::Char('p') => editor.pick(),
+                            KeyCode::Tab => editor.switch_team(),
+                            KeyCode::Char('s') if !editor.toggle_spawn() => {
+                                editor_status = format!("No spawn point for {:?} fits there", editor.team);
+                            },
+                            KeyCode::Char('m') => {
+                                editor.mirror();
+                                editor_status = "Mirrored the half under the cursor".to_string();
+                            },
+                            KeyCode::Char('c') => {
+                                show_asymmetries = !show_asymmetries;
+                                let odd = editor.map.asymmetries().len();
+                                editor_status = match (show_asymmetries, odd) {
+                                    (false, _) => "Symmetry check off".to_string(),
+                                    (true, 0) => "Both sides match".to_string(),
+                                    (true, n) => format!("{n} cells differ from the other side, marked !"),
+                                };
+                            },
+                            KeyCode::Char('w') => {
+                                editor_status = match editor.save() {
+                                    Ok(()) => format!("Saved {}", editor.path.display()),
+                                    Err(e) => e.to_string(),
+                                };
+                            },
+                            KeyCode::Char('o') => {
+                                print_at(20, 1, "Map file to edit : ".to_string());
+                                let path = match read_line("") {
+                                    Ok(n) => PathBuf::from(n.trim()),
+                                    Err(_) => PathBuf::new()
+                                };
+                                print_at(20, 1, " ".repeat(50));
+
+                                if let Some(opened) = open_editor(&path) {
+                                    *editor = opened;
+                                    editor_status = "Editing, w to save".to_string();
+                                    cls();
+                                }
+                            },
+                            KeyCode::Char('q') if editor.dirty && !leaving => {
+                                editor_status = "Unsaved changes, q again to leave anyway".to_string();
+                            },
+                            KeyCode::Char('q') => {
+                                map_editor = None;
+                                cls();
+                                command_state = CommandState::Menu;
+                            },
+                            _ => (),
+                        }
+                        if leaving && key_code != KeyCode::Null && key_code != KeyCode::Char('q') {
+                            editor_status.clear();
+                        }
                     },
-                    CommandState::Chat => todo!(),
                 }
 
             }
@@ -186,12 +536,231 @@ async fn events(listening_port: u16) {
     }
 }
 
+// ticks per drawn frame at each fast forward setting, as a multiple of real time
+const REPLAY_SPEEDS: [u64; 4] = [1, 2, 4, 8];
+
+async fn watch_replay(replay: Replay) {
+    let mut playback = Playback::new(replay);
+    let mut reader = EventStream::new();
+
+    // frames are drawn ten times a second, like the game itself
+    let tick_rate = playback.replay().tick_rate.max(1) as u64;
+    let ticks_per_frame = (tick_rate / 10).max(1);
+    let length = playback.replay().length;
+    let mut paused = false;
+    let mut speed = 0;
+
+    loop {
+        let mut delay = Delay::new(Duration::from_millis(100)).fuse();
+        let mut term_event = reader.next().fuse();
+
+        select! {
+            _ = delay => {
+                if !paused {
+                    for _ in 0..ticks_per_frame * REPLAY_SPEEDS[speed] {
+                        playback.step();
+                    }
+                }
+            },
+            term_handler = term_event => {
+                let key_code = match term_handler {
+                    Some(Ok(Event::Key(key))) => key.code,
+                    Some(Ok(Event::Resize(_, _))) => {
+                        cls();
+                        KeyCode::Null
+                    },
+                    Some(Ok(_)) => KeyCode::Null,
+                    Some(Err(e)) => {
+                        println!("Error: {:?}\r", e);
+                        KeyCode::Null
+                    },
+                    None => break,
+                };
+
+                let tick = playback.tick();
+                match key_code {
+                    KeyCode::Char(' ') => paused = !paused,
+                    // stepping only makes sense while paused, otherwise the next frame runs straight on
+                    KeyCode::Char('.') | KeyCode::Right => {
+                        paused = true;
+                        playback.step();
+                    },
+                    KeyCode::Char(',') | KeyCode::Left => playback.seek(tick.saturating_sub(tick_rate)),
+                    KeyCode::Char(']') => playback.seek(tick + tick_rate * 10),
+                    KeyCode::Char('[') => playback.seek(tick.saturating_sub(tick_rate * 10)),
+                    KeyCode::Char('f') => speed = (speed + 1) % REPLAY_SPEEDS.len(),
+                    KeyCode::Char(c @ '0'..='9') => {
+                        let tenth = c.to_digit(10).unwrap() as u64;
+                        playback.seek(length * tenth / 10);
+                    },
+                    KeyCode::Home => playback.seek(0),
+                    KeyCode::End => playback.seek(length),
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    _ => (),
+                }
+            }
+        };
+
+        let world = playback.world();
+        print_at(0, 0, format!("Replay, seed {}", playback.replay().seed));
+        print_at(
+            1,
+            1,
+            format!(
+                "Tick {}/{}  |  Green {}    Red {}  |  {:<20}",
+                playback.tick(),
+                length,
+                world.living_count(Team::Green),
+                world.living_count(Team::Red),
+                if paused {
+                    "Paused".to_string()
+                } else {
+                    format!("Playing x{}", REPLAY_SPEEDS[speed])
+                }
+            ),
+        );
+        render_grid(5, 4, world.terrain());
+        render_grid_pieces(5, 4, world.pieces());
+        if let Some(outcome) = world
+            .outcome(playback.replay().win_condition)
+            .filter(|_| playback.at_end())
+        {
+            render_outcome(5, 4, world.terrain(), outcome);
+        }
+        print_at(4, 6 + world.terrain().height() as u16, "space pause   . step   , back a second   [ ] ten seconds   0-9 jump   f faster   q quit");
+        stdout().flush().unwrap();
+    }
+}
+
+const USAGE: &str = "usage: fracas [--connect <host:port> [--join <game code> | --map <name>] | --units <path> --maps <dir>] [--name <name>]\n       fracas --replay <path>";
+
+struct Options {
+    connect: Option<String>,
+    join: Option<String>,
+    name: Option<String>,
+    units: Option<PathBuf>,
+    map: Option<String>,
+    maps: Option<PathBuf>,
+    replay: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> std::result::Result<Options, String> {
+    let mut options = Options {
+        connect: None,
+        join: None,
+        name: None,
+        units: None,
+        map: None,
+        maps: None,
+        replay: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+
+        match arg.as_str() {
+            "--connect" => options.connect = Some(value()?),
+            "--join" => options.join = Some(value()?),
+            "--name" => options.name = Some(value()?),
+            "--units" => options.units = Some(PathBuf::from(value()?)),
+            "--map" => options.map = Some(value()?),
+            "--maps" => options.maps = Some(PathBuf::from(value()?)),
+            "--replay" => options.replay = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("unknown argument {arg}")),
+        }
+    }
+
+    if options.join.is_some() && options.connect.is_none() {
+        return Err("--join needs --connect to say which server the game is on".to_string());
+    }
+    if options.replay.is_some()
+        && (options.connect.is_some() || options.units.is_some() || options.maps.is_some())
+    {
+        return Err("--replay plays a file on its own, without a server".to_string());
+    }
+    if options.units.is_some() && options.connect.is_some() {
+        return Err(
+            "--units is for the local server, a remote one uses its own catalogue".to_string(),
+        );
+    }
+    if options.maps.is_some() && options.connect.is_some() {
+        return Err("--maps is for the local server, a remote one has its own maps".to_string());
+    }
+    // on the local server the map is picked from the menu
+    if options.map.is_some() && (options.connect.is_none() || options.join.is_some()) {
+        return Err(
+            "--map picks the map for a new game on a --connect server, joining takes the host's"
+                .to_string(),
+        );
+    }
+
+    Ok(options)
+}
+
 fn main() -> Result<()> {
-    let listener = block_on(async { TcpListener::bind("127.0.0.1:0").await.unwrap() });
-    let listening_port = listener.local_addr().unwrap().port();
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    // a replay is played back right here, there is no server or session involved
+    if let Some(path) = &options.replay {
+        let replay = match Replay::load(path) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                std::process::exit(1);
+            }
+        };
+        return in_terminal(watch_replay(replay));
+    }
+
+    // without --connect the client hosts its own server in process, as it always has
+    let (server_address, autostart) = match options.connect {
+        Some(address) => {
+            let first = match options.join {
+                Some(code) => ClientMessage::JoinGame { code },
+                None => ClientMessage::NewGame { map: options.map },
+            };
+            (address, Some(first))
+        }
+        None => {
+            let listener = block_on(async { TcpListener::bind("127.0.0.1:0").await.unwrap() });
+            let listening_port = listener.local_addr().unwrap().port();
+            let mut config = ServerConfig::default();
+            if let Some(path) = options.units {
+                config.catalogue = match Catalogue::load(&path) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        eprintln!("{}: {e}", path.display());
+                        std::process::exit(1);
+                    }
+                };
+            }
+            if let Some(dir) = options.maps {
+                config.maps = match Map::load_dir(&dir) {
+                    Ok(m) => m,
+                    Err((path, e)) => {
+                        eprintln!("{}: {e}", path.display());
+                        std::process::exit(1);
+                    }
+                };
+            }
+            spawn(server(listener, config));
+            (format!("localhost:{listening_port}"), None)
+        }
+    };
 
-    spawn(server(listener));
+    // other players see this next to your chat lines
+    let name = options.name.or_else(|| std::env::var("USER").ok());
+
+    in_terminal(events(server_address, autostart, name))
+}
 
+fn in_terminal(run: impl Future<Output = ()>) -> Result<()> {
     enable_raw_mode()?;
 
     execute!(stdout(), EnableMouseCapture, Hide)?;
@@ -199,7 +768,7 @@ fn main() -> Result<()> {
     color_set(Color::Reset, Color::Black);
     cls();
 
-    block_on(events(listening_port));
+    block_on(run);
 
     execute!(stdout(), DisableMouseCapture, Show)?;
 
@@ -211,29 +780,182 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn render_grid(x: u16, y: u16, command_state: &CommandState) {
-    // play area is a 70x20
-    print_at(0, 0, format!("{:?}         ", command_state));
+fn render_grid(x: u16, y: u16, terrain: &Terrain) {
+    // play area is as big as the map says
+    let (width, height) = (terrain.width() as u16, terrain.height() as u16);
+    rect_outline('█', x - 1, y - 1, width + 2, height + 2);
+
+    // each run of the same tile along a row goes out in one go
+    for (row, tiles) in terrain.rows().iter().enumerate() {
+        let tiles: Vec<Tile> = tiles.chars().filter_map(Tile::from_code).collect();
+        let mut start = 0;
+        for run in tiles.chunk_by(|a, b| a == b) {
+            let (glyph, colour) = tile_look(run[0]);
+            color_set(colour, Color::Black);
+            print_at(
+                x + start as u16,
+                y + row as u16,
+                glyph.to_string().repeat(run.len()),
+            );
+            start += run.len();
+        }
+    }
+
+    color_reset();
+}
+
+fn tile_look(tile: Tile) -> (char, Color) {
+    match tile {
+        Tile::Open => (' ', Color::Reset),
+        Tile::Wall => ('█', Color::Grey),
+        Tile::Forest => ('♣', Color::DarkGreen),
+        Tile::Water => ('≈', Color::Blue),
+        Tile::Road => ('░', Color::DarkYellow),
+    }
+}
+
+// None leaves it to the server
+fn ask_map() -> Option<String> {
+    print_at(20, 1, "Map (blank for the server's choice) : ".to_string());
+    let map = match read_line("") {
+        Ok(n) => Some(n.trim().to_string()).filter(|n| !n.is_empty()),
+        Err(_) => None,
+    };
+    print_at(20, 1, " ".repeat(50));
+    map
+}
+
+// loads the file, or starts a blank map there if nothing is, printing what went wrong if neither works
+fn open_editor(path: &Path) -> Option<MapEditor> {
+    if path.as_os_str().is_empty() {
+        return None;
+    }
+
+    let (mut width, mut height) = (FIELD_WIDTH, FIELD_HEIGHT);
+    if !path.exists() {
+        print_at(20, 1, format!("New map size ({width}x{height}) : "));
+        let size = read_line("").unwrap_or_default();
+        print_at(20, 1, " ".repeat(50));
+        if let Some((w, h)) = size.trim().split_once('x') {
+            width = w.parse().unwrap_or(width).clamp(1, MAX_WIDTH);
+            height = h.parse().unwrap_or(height).clamp(1, MAX_HEIGHT);
+        }
+    }
+
+    match MapEditor::open(path, width, height) {
+        Ok(editor) => Some(editor),
+        Err(e) => {
+            print_at(50, 0, format!("{}: {e}", path.display()));
+            None
+        }
+    }
+}
+
+fn render_editor(x: u16, y: u16, editor: &MapEditor, show_asymmetries: bool, status: &str) {
+    let map = &editor.map;
+    render_grid(x, y, &map.tiles);
+
+    // both sides' spawn points, numbered by the key that spawns there
+    for zone in &map.spawns {
+        color_set(team_colour(zone.team), Color::Black);
+        for (i, (px, py)) in zone.points.iter().enumerate() {
+            print_at(x + *px as u16, y + *py as u16, i + 1);
+        }
+    }
+    color_set(Color::Yellow, Color::Black);
+    for objective in &map.objectives {
+        print_at(x + objective.x as u16, y + objective.y as u16, '◆');
+    }
+
+    if show_asymmetries {
+        color_set(Color::Magenta, Color::Black);
+        for (cx, cy) in map.asymmetries() {
+            print_at(x + cx as u16, y + cy as u16, '!');
+        }
+    }
+
+    // what the next paint would cover, drawn in the brush on a grey background
+    let (glyph, colour) = tile_look(editor.brush);
+    let (cx, cy) = (x + editor.cursor.0 as u16, y + editor.cursor.1 as u16);
+    color_set(colour, Color::DarkGrey);
+    match (editor.tool, editor.anchor) {
+        (Tool::Line, Some((ax, ay))) => draw_line(glyph, x + ax as u16, y + ay as u16, cx, cy),
+        (Tool::Rect, Some((ax, ay))) => {
+            let (ax, ay) = (x + ax as u16, y + ay as u16);
+            rect_filled(
+                &glyph.to_string(),
+                ax.min(cx),
+                ay.min(cy),
+                ax.abs_diff(cx) + 1,
+                ay.abs_diff(cy) + 1,
+            );
+        }
+        _ => print_at(cx, cy, glyph),
+    }
+    color_reset();
+
+    let below = y + map.height as u16 + 2;
+    let title = format!(
+        "{}{}  |  {}  |  {:?} {:?}  |  placing {:?}  |  {},{}",
+        map.name,
+        if editor.dirty { "*" } else { "" },
+        editor.path.display(),
+        editor.tool,
+        editor.brush,
+        editor.team,
+        editor.cursor.0,
+        editor.cursor.1
+    );
+    print_at(
+        x - 1,
+        below,
+        format!("{:<width$}", title, width = LINE_WIDTH),
+    );
+    print_at(
+        x - 1,
+        below + 1,
+        format!("{:<width$}", status, width = LINE_WIDTH),
+    );
+    print_at(
+        x - 1,
+        below + 3,
+        "arrows/mouse move   space/click paint   1-5 open wall forest water road   p pick",
+    );
+    print_at(
+        x - 1,
+        below + 4,
+        "b brush   l line   r rectangle   esc cancel   s spawn point   tab switch side",
+    );
+    print_at(
+        x - 1,
+        below + 5,
+        "m mirror   c check symmetry   w save   o open   q quit",
+    );
+}
+
+fn render_map_marks(x: u16, y: u16, map: &Map, team: Team) {
+    // !!! IMPORTANT: render_grid() MUST be called first, and render_grid_pieces() after
+
+    // your side's spawn points, numbered by the key that spawns there
+    color_set(team_colour(team), Color::Black);
+    for (i, (px, py)) in map.spawn_points(team).iter().enumerate() {
+        print_at(x + *px as u16, y + *py as u16, i + 1);
+    }
 
-    rect_outline('█', x - 1, y - 1, 70 + 2, 20 + 2);
-    for i in 1..10 {
-        print_at(x - 1, y + (i * 2), i.to_string());
-        //print_at(x+71, y+(i*2), i.to_string());
+    color_set(Color::Yellow, Color::Black);
+    for objective in &map.objectives {
+        print_at(x + objective.x as u16, y + objective.y as u16, '◆');
     }
-    //color_set(Color::White, Color::Black);
-    rect_filled(" ", x, y, 70, 20);
-    draw_line('░', x + 5, y, x + 5, y + 19);
-    draw_line('░', x + 65, y, x + 65, y + 19);
 
-    //color_reset();
+    color_reset();
 }
 
-fn render_grid_pieces(x: u16, y: u16, pieces: &Vec<Character>) {
+fn render_grid_pieces(x: u16, y: u16, pieces: &[Character]) {
     // !!! IMPORTANT: render_grid() MUST be called first
 
     for p in pieces {
         if p.hp > 0 {
-            color_set(p.color, Color::Black);
+            color_set(team_colour(p.team), Color::Black);
             print_at(x + p.x as u16, y + p.y as u16, p.denotation);
         }
     }
@@ -241,6 +963,98 @@ fn render_grid_pieces(x: u16, y: u16, pieces: &Vec<Character>) {
     color_reset();
 }
 
+fn phase_status(phase: &MatchPhase) -> String {
+    match phase {
+        MatchPhase::Lobby => "Lobby, press r when ready".to_string(),
+        MatchPhase::Countdown { seconds_left } => format!("Battle starts in {seconds_left}"),
+        MatchPhase::Running => "Fight!".to_string(),
+        MatchPhase::Finished(_) => "Over, press r for a rematch".to_string(),
+    }
+}
+
+// what you have to spend and what each unit costs
+fn gold_status(gold: Option<(u32, u32)>, catalogue: &Catalogue) -> String {
+    let prices: Vec<String> = catalogue
+        .units
+        .iter()
+        .map(|u| format!("{} {}", u.key, u.cost))
+        .collect();
+    match gold {
+        Some((gold, income)) => format!("Gold {gold} (+{income}/s)  {}", prices.join("  ")),
+        None => prices.join("  "),
+    }
+}
+
+fn render_outcome(x: u16, y: u16, terrain: &Terrain, outcome: Outcome) {
+    // over the middle of the play area, the pieces stay visible around it
+    let (banner, colour) = match outcome {
+        Outcome::Won(team) => (format!(" {:?} wins! ", team), team_colour(team)),
+        Outcome::Draw => (" It's a draw! ".to_string(), Color::White),
+    };
+
+    color_set(colour, Color::Black);
+    let (width, height) = (terrain.width() as u16, terrain.height() as u16);
+    print_at(
+        (x + width / 2).saturating_sub(banner.chars().count() as u16 / 2),
+        y + height / 2 - 1,
+        banner,
+    );
+    color_reset();
+}
+
+fn render_units(x: u16, y: u16, catalogue: &Catalogue) {
+    let legend: Vec<String> = catalogue
+        .units
+        .iter()
+        .map(|u| format!("{} {} ({}) {}g", u.key, u.name, u.denotation, u.cost))
+        .collect();
+    print_at(
+        x,
+        y,
+        format!("{:<width$}", legend.join("   "), width = LINE_WIDTH),
+    );
+}
+
+fn render_chat(x: u16, y: u16, lines: &VecDeque<ChatLine>, scroll: usize, typing: Option<&str>) {
+    let end = lines.len() - scroll.min(lines.len());
+    let start = end.saturating_sub(CHAT_PANE_LINES);
+
+    for row in 0..CHAT_PANE_LINES {
+        let row_y = y + row as u16;
+        print_at(x, row_y, " ".repeat(CHAT_PANE_WIDTH));
+
+        if let Some(line) = lines.get(start + row).filter(|_| start + row < end) {
+            let name: String = line.name.chars().take(CHAT_PANE_WIDTH).collect();
+            let room = CHAT_PANE_WIDTH.saturating_sub(name.chars().count() + 2);
+            let text: String = line.text.chars().take(room).collect();
+
+            color_set(team_colour(line.team), Color::Black);
+            print_at(x, row_y, &name);
+            color_reset();
+            print_at(x + name.chars().count() as u16, row_y, format!(": {text}"));
+        }
+    }
+
+    // while typing show the end of the line, that is where the cursor is
+    let prompt = match typing {
+        Some(input) => {
+            let room = CHAT_PANE_WIDTH - 6;
+            let shown: String = input
+                .chars()
+                .skip(input.chars().count().saturating_sub(room))
+                .collect();
+            format!("Say: {shown}_")
+        }
+        None if scroll > 0 => format!("-- {scroll} newer lines below --"),
+        None => "t to chat".to_string(),
+    };
+    print_at(
+        x,
+        y + CHAT_PANE_LINES as u16,
+        format!("{:<width$}", prompt, width = CHAT_PANE_WIDTH),
+    );
+}
+
 fn cls() {
     queue!(stdout(), Clear(ClearType::All)).unwrap();
 }
@@ -249,6 +1063,14 @@ fn print_at<T: Display>(x: u16, y: u16, s: T) {
     queue!(stdout(), MoveTo(x, y), Print(s),).unwrap();
 }
 
+// the lib only knows the sides by name, this is how they look on screen
+fn team_colour(team: Team) -> Color {
+    match team {
+        Team::Green => Color::Green,
+        Team::Red => Color::Red,
+    }
+}
+
 fn color_set(fg: Color, bg: Color) {
     queue!(
         stdout(),
@@ -296,49 +1118,6 @@ fn draw_line(draw: char, x1: u16, y1: u16, x2: u16, y2: u16) {
     }
 }
 
-fn calc_line(x1: i32, y1: i32, x2: i32, y2: i32) -> Vec<(i32, i32)> {
-    let mut coordinates: Vec<(i32, i32)> = vec![];
-    let dx = i32::abs(x2 - x1);
-    let dy = i32::abs(y2 - y1);
-    let sx = {
-        if x1 < x2 {
-            1
-        } else {
-            -1
-        }
-    };
-    let sy = {
-        if y1 < y2 {
-            1
-        } else {
-            -1
-        }
-    };
-
-    let mut error = (if dx > dy { dx } else { -dy }) / 2;
-    let mut current_x = x1;
-    let mut current_y = y1;
-    loop {
-        coordinates.push((current_x, current_y));
-
-        if current_x == x2 && current_y == y2 {
-            break;
-        }
-
-        let error2 = error;
-
-        if error2 > -dx {
-            error -= dy;
-            current_x += sx;
-        }
-        if error2 < dy {
-            error += dx;
-            current_y += sy;
-        }
-    }
-    coordinates
-}
-
 async fn logging(s: String) {
     let mut file = async_std::fs::OpenOptions::new()
         .write(true)
@@ -352,64 +1131,118 @@ async fn logging(s: String) {
     let _ = AsyncWriteExt::write_all(&mut file, log.as_bytes()).await;
 }
 
-async fn logging_tail() {
-    let mut file = match async_std::fs::OpenOptions::new()
-        .read(true)
-        .open("logging.txt")
-        .await
-    {
-        Ok(f) => f,
-        Err(_) => return, // file not found, nothing to show
-    };
-
-    let mut output = String::with_capacity(1_000_000);
-
-    let _ = AsyncReadExt::read_to_string(&mut file, &mut output).await;
-
-    let lines: Vec<&str> = output.split_terminator('\n').collect();
-
-    let mut start_line = lines.len();
-    if start_line > 20 {
-        start_line -= 20;
+fn render_combat_log(x: u16, y: u16, log: &CombatLog, catalogue: &Catalogue) {
+    let page = log.page(COMBAT_PANE_LINES);
+    for row in 0..COMBAT_PANE_LINES {
+        let row_y = y + row as u16;
+        print_at(x, row_y, " ".repeat(COMBAT_PANE_WIDTH));
+
+        if let Some((tick, event)) = page.get(row) {
+            let line: String = format!("{tick:>6}  {event}")
+                .chars()
+                .take(COMBAT_PANE_WIDTH)
+                .collect();
+            color_set(team_colour(event.actor().team), Color::Black);
+            print_at(x, row_y, line);
+            color_reset();
+        }
     }
 
-    let mut j = 0;
-    for i in start_line..lines.len() {
-        print_at(80, (j + 4) as u16, format!("{:<50}", lines[i]));
-        j += 1;
+    let filter = log.filter();
+    let team = filter
+        .team
+        .map_or("both sides".to_string(), |team| format!("{:?}", team));
+    let unit = filter
+        .unit
+        .map(|d| {
+            catalogue
+                .units
+                .iter()
+                .find(|u| u.denotation == d)
+                .map_or(d.to_string(), |u| u.name.clone())
+        })
+        .unwrap_or_else(|| "all units".to_string());
+    let kind = match filter.kind {
+        None => "everything",
+        Some(EventKind::Hit) => "hits only",
+        Some(EventKind::Killed) => "kills only",
+        Some(EventKind::Miss) => "misses only",
+        Some(_) => "some",
+    };
+    let status = match log.scroll() {
+        0 => format!("F1 {team}  F2 {unit}  F3 {kind}"),
+        n => format!("-- {n} newer below, End to catch up --"),
+    };
+    let bottom = y + COMBAT_PANE_LINES as u16;
+    print_at(
+        x,
+        bottom,
+        format!("{:<width$}", status, width = COMBAT_PANE_WIDTH),
+    );
+    print_at(
+        x,
+        bottom + 1,
+        format!(
+            "{:<width$}",
+            "PgUp/PgDn to scroll the combat log",
+            width = COMBAT_PANE_WIDTH
+        ),
+    );
+}
+
+// the next of the options, or none after the last of them
+fn cycle<T: Copy + PartialEq>(current: Option<T>, options: &[T]) -> Option<T> {
+    match current.and_then(|c| options.iter().position(|o| *o == c)) {
+        None => options.first().copied(),
+        Some(i) => options.get(i + 1).copied(),
     }
 }
 
-async fn callb(send: &[u8], address: &String) -> Vec<u8> {
+async fn connect(address: &str) -> Option<(TcpStream, UnboundedReceiver<ServerMessage>)> {
     match TcpStream::connect(address).await {
         Ok(stream) => {
             print_at(35, 0, "           ");
-            let mut stream = stream;
-            match AsyncWriteExt::write_all(&mut stream, send).await {
-                Ok(_) => (),
-                Err(e) => logging(format!("👄 Err Write {:?}", e)).await,
-            }
-
-            let mut buf = vec![0u8; 1024];
-            let n = match AsyncReadExt::read(&mut stream, &mut buf).await {
-                Ok(n) => n,
-                Err(e) => {
-                    logging(format!("👄 Err Write {:?}", e)).await;
-                    0
+            let (pushed, messages) = unbounded();
+            let reader = stream.clone();
+            spawn(async move {
+                if let Err(e) = receive_messages(reader, pushed).await {
+                    logging(format!("👄 Err Read {:?}", e)).await;
                 }
-            };
-            buf.truncate(n);
-            buf.to_vec()
+            });
+            Some((stream, messages))
         }
         Err(_) => {
             print_at(35, 0, "!Network 📶!");
-            vec![]
+            None
         }
     }
 }
 
-async fn call(send: &[u8], address: &String) -> String {
-    String::from_utf8(callb(send, address).await).unwrap()
+async fn open_session(
+    address: &str,
+    first: &ClientMessage,
+    name: Option<&str>,
+) -> Option<(TcpStream, UnboundedReceiver<ServerMessage>)> {
+    let (mut stream, messages) = connect(address).await?;
+
+    let set_name = name.map(|name| ClientMessage::SetName {
+        name: name.to_string(),
+    });
+    for message in std::iter::once(first).chain(set_name.as_ref()) {
+        if let Err(e) = send_message(&mut stream, message).await {
+            logging(format!("👄 Err Write {:?}", e)).await;
+            return None;
+        }
+    }
+    Some((stream, messages))
+}
+
+async fn send(connection: &mut Option<TcpStream>, message: &ClientMessage) {
+    if let Some(stream) = connection.as_mut() {
+        if let Err(e) = send_message(stream, message).await {
+            logging(format!("👄 Err Write {:?}", e)).await;
+        }
+    }
 }
 
 pub fn read_line(s: &str) -> Result<String> {
@@ -433,12 +1266,10 @@ pub fn read_line(s: &str) -> Result<String> {
                 execute!(stdout(), Print(c),).unwrap();
                 line.push(c);
             }
-            KeyCode::Backspace => {
-                if line.len() > 0 {
-                    execute!(stdout(), MoveLeft(1), Print(' '), MoveLeft(1),).unwrap();
+            KeyCode::Backspace if !line.is_empty() => {
+                execute!(stdout(), MoveLeft(1), Print(' '), MoveLeft(1),).unwrap();
 
-                    line.pop();
-                }
+                line.pop();
             }
             _ => {}
         }
@@ -446,4 +1277,3 @@ pub fn read_line(s: &str) -> Result<String> {
 
     Ok(line)
 }
-
@@ -0,0 +1,263 @@
+use std::{
+    collections::HashSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::terrain::*;
+use crate::Team;
+
+// the map shipped with the game, the open field battles have always been fought on
+pub const BUILT_IN: &str = include_str!("../maps/classic.ron");
+
+// spawn points are picked with the number keys, 1 to 9
+pub const MAX_SPAWN_POINTS: usize = 9;
+
+// anything bigger does not fit in a terminal alongside the rest of the screen
+pub const MAX_WIDTH: i16 = 100;
+pub const MAX_HEIGHT: i16 = 30;
+
+// every map has to make room for both sides
+pub const TEAMS: [Team; 2] = [Team::Green, Team::Red];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Map {
+    pub name: String,
+    pub width: i16,
+    pub height: i16,
+    pub tiles: Terrain,
+    pub spawns: Vec<SpawnZone>,
+    #[serde(default)]
+    pub objectives: Vec<Objective>,
+}
+
+// where one side's units come on, the first point is spawn row 1 and so on
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpawnZone {
+    pub team: Team,
+    pub points: Vec<(i16, i16)>,
+}
+
+// a spot worth fighting over, marked on the field for everyone to see
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Objective {
+    pub name: String,
+    pub x: i16,
+    pub y: i16,
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    // the file parsed but describes a field nobody could play on
+    Invalid(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "could not read the map: {e}"),
+            MapError::Parse(e) => write!(f, "could not parse the map: {e}"),
+            MapError::Invalid(reason) => write!(f, "bad map: {reason}"),
+        }
+    }
+}
+
+// replays from before maps were fought on the built in one
+impl Default for Map {
+    fn default() -> Self {
+        Map::built_in().clone()
+    }
+}
+
+impl Map {
+    pub fn built_in() -> &'static Map {
+        static MAP: OnceLock<Map> = OnceLock::new();
+        MAP.get_or_init(|| Map::parse(BUILT_IN).expect("maps/classic.ron is broken"))
+    }
+
+    // open ground and no spawn points yet, somewhere to start drawing
+    pub fn blank(name: &str, width: i16, height: i16) -> Map {
+        Map {
+            name: name.to_string(),
+            width,
+            height,
+            tiles: Terrain::open(width, height),
+            spawns: TEAMS
+                .iter()
+                .map(|&team| SpawnZone {
+                    team,
+                    points: Vec::new(),
+                })
+                .collect(),
+            objectives: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Map, MapError> {
+        let text = fs::read_to_string(path).map_err(MapError::Io)?;
+        Map::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Map, MapError> {
+        let map: Map = ron::from_str(text).map_err(MapError::Parse)?;
+        map.validate().map_err(MapError::Invalid)?;
+        Ok(map)
+    }
+
+    // only maps that would load again are written
+    pub fn save(&self, path: &Path) -> Result<(), MapError> {
+        self.validate().map_err(MapError::Invalid)?;
+        let config = ron::ser::PrettyConfig::new().depth_limit(2);
+        let text = ron::ser::to_string_pretty(self, config).expect("maps always serialize");
+        fs::write(path, text + "\n").map_err(MapError::Io)
+    }
+
+    // every .ron file in the directory, by file name, after the built in map unless one of them
+    // takes its name; the error says which file was at fault
+    pub fn load_dir(dir: &Path) -> Result<Vec<Map>, (PathBuf, MapError)> {
+        let entries = fs::read_dir(dir).map_err(|e| (dir.to_path_buf(), MapError::Io(e)))?;
+        let mut paths = entries
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<PathBuf>, io::Error>>()
+            .map_err(|e| (dir.to_path_buf(), MapError::Io(e)))?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "ron"));
+        paths.sort();
+
+        let mut maps = vec![Map::built_in().clone()];
+        let mut names = HashSet::new();
+        for path in paths {
+            let map = Map::load(&path).map_err(|e| (path.clone(), e))?;
+            if !names.insert(map.name.clone()) {
+                let reason = format!("another map is already called {}", map.name);
+                return Err((path, MapError::Invalid(reason)));
+            }
+            match maps.iter().position(|m| m.name == map.name) {
+                Some(i) => maps[i] = map,
+                None => maps.push(map),
+            }
+        }
+        Ok(maps)
+    }
+
+    pub fn spawn_points(&self, team: Team) -> &[(i16, i16)] {
+        self.spawns
+            .iter()
+            .find(|zone| zone.team == team)
+            .map_or(&[], |zone| &zone.points[..])
+    }
+
+    // rows count from 1, like the keys that pick them
+    pub fn spawn_point(&self, team: Team, row: u8) -> Option<(i16, i16)> {
+        let row = (row as usize).checked_sub(1)?;
+        self.spawn_points(team).get(row).copied()
+    }
+
+    // the cells that differ from their mirror image across the middle of the map: tiles, spawn
+    // points that the other side does not have in the same place under the same key, and
+    // objectives with nothing across from them
+    pub fn asymmetries(&self) -> Vec<(i16, i16)> {
+        let mirror = |x: i16| self.width - 1 - x;
+        let mut cells = Vec::new();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.tiles.tile(x, y) != self.tiles.tile(mirror(x), y) {
+                    cells.push((x, y));
+                }
+            }
+        }
+
+        let (green, red) = (self.spawn_points(TEAMS[0]), self.spawn_points(TEAMS[1]));
+        for i in 0..green.len().max(red.len()) {
+            match (green.get(i), red.get(i)) {
+                (Some(&(gx, gy)), Some(&(rx, ry))) if (mirror(gx), gy) == (rx, ry) => (),
+                (g, r) => cells.extend(g.into_iter().chain(r).copied()),
+            }
+        }
+
+        // one in the middle is as near to either side, even when the width leaves no middle column
+        for objective in &self.objectives {
+            let across = (mirror(objective.x), objective.y);
+            let middle = (objective.x - across.0).abs() <= 1;
+            if !middle && !self.objectives.iter().any(|o| (o.x, o.y) == across) {
+                cells.push((objective.x, objective.y));
+            }
+        }
+
+        cells.sort_unstable_by_key(|&(x, y)| (y, x));
+        cells.dedup();
+        cells
+    }
+
+    // a field that fits on the screen, with one spawn zone a side on open ground and every
+    // objective on the field
+    pub fn validate(&self) -> Result<(), String> {
+        let name = &self.name;
+        if name.trim().is_empty() {
+            return Err("the map has no name".to_string());
+        }
+        if !(1..=MAX_WIDTH).contains(&self.width) || !(1..=MAX_HEIGHT).contains(&self.height) {
+            return Err(format!(
+                "{name} is {}x{}, maps go up to {MAX_WIDTH}x{MAX_HEIGHT}",
+                self.width, self.height
+            ));
+        }
+        if (self.tiles.width(), self.tiles.height()) != (self.width, self.height) {
+            return Err(format!(
+                "{name} says it is {}x{} but its tiles are {}x{}",
+                self.width,
+                self.height,
+                self.tiles.width(),
+                self.tiles.height()
+            ));
+        }
+
+        let mut taken = HashSet::new();
+        for team in TEAMS {
+            let zones = self.spawns.iter().filter(|zone| zone.team == team).count();
+            if zones != 1 {
+                return Err(format!(
+                    "{name} needs one spawn zone for {team:?}, not {zones}"
+                ));
+            }
+            let points = self.spawn_points(team);
+            if !(1..=MAX_SPAWN_POINTS).contains(&points.len()) {
+                return Err(format!(
+                    "{name} gives {team:?} {} spawn points, it needs 1 to {MAX_SPAWN_POINTS}",
+                    points.len()
+                ));
+            }
+            for &(x, y) in points {
+                if !self.tiles.passable(x, y) {
+                    return Err(format!(
+                        "{name} has a {team:?} spawn at {x},{y} that is off the field or blocked"
+                    ));
+                }
+                if !taken.insert((x, y)) {
+                    return Err(format!("{name} has two spawns at {x},{y}"));
+                }
+            }
+        }
+
+        let mut objectives = HashSet::new();
+        for objective in &self.objectives {
+            let (x, y) = (objective.x, objective.y);
+            if objective.name.trim().is_empty() {
+                return Err(format!("{name} has an objective with no name at {x},{y}"));
+            }
+            if !objectives.insert(&objective.name) {
+                return Err(format!("{name} lists {} twice", objective.name));
+            }
+            if !self.tiles.contains(x, y) {
+                return Err(format!("{name} has {} off the field", objective.name));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::{Error, ErrorKind, Result};
+
+use futures::{channel::mpsc::UnboundedSender, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+// every frame on the wire is a 4 byte big endian length followed by that many payload bytes
+pub const FRAME_HEADER_LEN: usize = 4;
+
+// guard against a corrupt or hostile header making us allocate gigabytes
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+pub async fn write_frame<W>(writer: &mut W, payload: &[u8]) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "frame of {} bytes exceeds the {MAX_FRAME_LEN} byte limit",
+                payload.len()
+            ),
+        ));
+    }
+
+    // header and payload go out in one write, two small writes back to back stall on Nagle's algorithm
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+pub async fn read_frame<R>(reader: &mut R) -> Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    reader.read_exact(&mut header).await?;
+
+    let len = u32::from_be_bytes(header) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("frame of {len} bytes exceeds the {MAX_FRAME_LEN} byte limit"),
+        ));
+    }
+
+    // read_exact keeps reading until the whole payload has arrived, however the stream splits it up
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(payload)
+}
+
+pub async fn send_message<W, T>(writer: &mut W, message: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let payload =
+        bincode::serialize(message).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    write_frame(writer, &payload).await
+}
+
+// a frame that arrives intact but does not decode is reported as InvalidData
+pub async fn recv_message<R, T>(reader: &mut R) -> Result<T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let payload = read_frame(reader).await?;
+    bincode::deserialize(&payload).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+// hands on everything read until the other end hangs up or nobody is listening for it any more,
+// either of which is a clean end; any other read error is what stopped it
+pub async fn receive_messages<R, T>(mut reader: R, pushed: UnboundedSender<T>) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    loop {
+        match recv_message(&mut reader).await {
+            Ok(message) => {
+                if pushed.unbounded_send(message).is_err() {
+                    return Ok(());
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
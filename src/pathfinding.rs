@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+
+use crate::terrain::*;
+use crate::*;
+
+// one step in any of the eight directions costs the same, just as the old diagonal stepping did
+pub const NEIGHBOURS: [(i16, i16); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+// how many steps each cell is from the nearest enemy of one team, walking around that team's own units
+// and anything the terrain does not let anyone through
+pub struct FlowField {
+    width: i16,
+    height: i16,
+    distances: Vec<Option<u16>>,
+}
+
+impl FlowField {
+    pub fn towards_enemies_of(team: Team, pieces: &[Character], terrain: &Terrain) -> FlowField {
+        let (width, height) = (terrain.width(), terrain.height());
+        let cells = width as usize * height as usize;
+        let mut blocked = vec![false; cells];
+        let mut field = FlowField {
+            width,
+            height,
+            distances: vec![None; cells],
+        };
+        let mut frontier = VecDeque::new();
+
+        for p in pieces
+            .iter()
+            .filter(|p| p.hp > 0 && terrain.contains(p.x, p.y))
+        {
+            let cell = field.cell(p.x, p.y);
+            if p.team == team {
+                blocked[cell] = true;
+            } else if field.distances[cell].is_none() {
+                field.distances[cell] = Some(0);
+                frontier.push_back((p.x, p.y));
+            }
+        }
+
+        while let Some((x, y)) = frontier.pop_front() {
+            let next = field.distances[field.cell(x, y)].unwrap() + 1;
+            for (dx, dy) in NEIGHBOURS {
+                let (nx, ny) = (x + dx, y + dy);
+                if !terrain.passable(nx, ny) {
+                    continue;
+                }
+                let cell = field.cell(nx, ny);
+                if !blocked[cell] && field.distances[cell].is_none() {
+                    field.distances[cell] = Some(next);
+                    frontier.push_back((nx, ny));
+                }
+            }
+        }
+
+        field
+    }
+
+    // None off the field, on wall or water, or where the team's own units cut the cell off from every enemy
+    pub fn distance(&self, x: i16, y: i16) -> Option<u16> {
+        if (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+            self.distances[self.cell(x, y)]
+        } else {
+            None
+        }
+    }
+
+    fn cell(&self, x: i16, y: i16) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+}
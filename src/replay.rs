@@ -0,0 +1,206 @@
+use std::{fmt, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::catalogue::*;
+use crate::events::*;
+use crate::map::*;
+use crate::utils::*;
+use crate::world::*;
+use crate::Team;
+
+// everything needed to play one battle again: the seed, the units on offer and what was spawned when
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub tick_rate: u32,
+    pub win_condition: WinCondition,
+    pub catalogue: Catalogue,
+    // replays saved before there were maps were all fought on the built in one
+    #[serde(default)]
+    pub map: Map,
+    pub spawns: Vec<RecordedSpawn>,
+    // how many ticks the battle had run when it was saved
+    pub length: u64,
+}
+
+// tick is the world's tick when the spawn was accepted, it joins the field before the next step
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedSpawn {
+    pub tick: u64,
+    pub team: Team,
+    pub unit: String,
+    pub row: u8,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    // the replay asks for a unit its own catalogue does not have
+    UnknownUnit(String),
+    // or spawns on a row its own map does not have
+    RowOutOfRange(u8),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "could not read the replay: {e}"),
+            ReplayError::Parse(e) => write!(f, "could not parse the replay: {e}"),
+            ReplayError::UnknownUnit(unit) => write!(f, "the replay spawns an unknown unit {unit}"),
+            ReplayError::RowOutOfRange(row) => {
+                write!(f, "the replay spawns on a missing row {row}")
+            }
+        }
+    }
+}
+
+impl Replay {
+    pub fn new(
+        seed: u64,
+        tick_rate: u32,
+        win_condition: WinCondition,
+        catalogue: Catalogue,
+        map: Map,
+    ) -> Replay {
+        Replay {
+            seed,
+            tick_rate,
+            win_condition,
+            catalogue,
+            map,
+            spawns: Vec::new(),
+            length: 0,
+        }
+    }
+
+    pub fn record_spawn(&mut self, tick: u64, team: Team, unit: &str, row: u8) {
+        self.spawns.push(RecordedSpawn {
+            tick,
+            team,
+            unit: unit.to_string(),
+            row,
+        });
+        self.length = self.length.max(tick);
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let text = fs::read_to_string(path).map_err(ReplayError::Io)?;
+        let replay: Replay = ron::from_str(&text).map_err(ReplayError::Parse)?;
+
+        // checked up front so playback never has to give up half way through
+        for spawn in &replay.spawns {
+            if replay.catalogue.unit(&spawn.unit).is_none() {
+                return Err(ReplayError::UnknownUnit(spawn.unit.clone()));
+            }
+            if replay.map.spawn_point(spawn.team, spawn.row).is_none() {
+                return Err(ReplayError::RowOutOfRange(spawn.row));
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).unwrap()
+    }
+}
+
+// a server keeps its replay from the events it sends everyone else, the spawns are all it needs
+// since the seed takes care of everything after them
+impl EventSink for Replay {
+    fn record(&mut self, tick: u64, events: &[CombatEvent]) {
+        for event in events {
+            if let CombatEvent::Spawned { unit, at, .. } = event {
+                let name = self
+                    .catalogue
+                    .by_denotation(unit.denotation)
+                    .map(|u| u.name.clone());
+                let row = self
+                    .map
+                    .spawn_points(unit.team)
+                    .iter()
+                    .position(|p| p == at);
+                if let (Some(name), Some(row)) = (name, row) {
+                    self.record_spawn(tick, unit.team, &name, row as u8 + 1);
+                }
+            }
+        }
+    }
+}
+
+// steps a fresh world through a recorded battle using the same rules the server ran
+pub struct Playback {
+    replay: Replay,
+    world: World,
+    next_spawn: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        let mut world = World::with_seed(replay.seed);
+        world.set_terrain(replay.map.tiles.clone());
+        let mut playback = Playback {
+            world,
+            replay,
+            next_spawn: 0,
+        };
+        playback.spawn_due();
+        playback
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.world.tick()
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.tick() >= self.replay.length
+    }
+
+    // false once the end of the recording has been reached
+    pub fn step(&mut self) -> bool {
+        if self.at_end() {
+            return false;
+        }
+        self.world.step();
+        self.spawn_due();
+        true
+    }
+
+    // the dice cannot be rolled backwards, so going back means playing forward again from the start
+    pub fn seek(&mut self, tick: u64) {
+        let tick = tick.min(self.replay.length);
+        if tick < self.tick() {
+            *self = Playback::new(self.replay.clone());
+        }
+        while self.tick() < tick {
+            self.step();
+        }
+    }
+
+    fn spawn_due(&mut self) {
+        let tick = self.world.tick();
+        while let Some(spawn) = self
+            .replay
+            .spawns
+            .get(self.next_spawn)
+            .filter(|s| s.tick <= tick)
+        {
+            let unit = self.replay.catalogue.unit(&spawn.unit);
+            if let (Some(unit), Some((x, y))) =
+                (unit, self.replay.map.spawn_point(spawn.team, spawn.row))
+            {
+                self.world.spawn(generate_unit(unit, x, y, spawn.team));
+            }
+            self.next_spawn += 1;
+        }
+    }
+}
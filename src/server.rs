@@ -1,288 +1,828 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
 use async_std::{
     net::{TcpListener, TcpStream},
-    task::block_on,
+    task::spawn,
+};
+use futures::{
+    channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+    AsyncWriteExt, StreamExt,
 };
-use crossterm::style::Color;
-use futures::{pin_mut, select, AsyncReadExt, AsyncWriteExt, FutureExt};
 use futures_timer::Delay;
 
-use crate::lib::*;
+use crate::catalogue::*;
+use crate::delta::*;
+use crate::events::*;
+use crate::map::*;
+use crate::net::*;
+use crate::replay::*;
+use crate::timestep::*;
 use crate::utils::*;
+use crate::world::*;
+use crate::*;
+
+// how many outgoing messages may queue for one client before pushed snapshots are dropped for it
+const CLIENT_QUEUE_LEN: usize = 8;
+
+// replies are never dropped, a client this far behind on them is cut off instead
+const CLIENT_BACKLOG_LEN: usize = 4_096;
+
+// how every match on this server is played, taken from the ServerConfig
+#[derive(Clone, Copy, Default)]
+struct MatchRules {
+    // when set every new match uses it, so a reported bug can be replayed exactly
+    seed: Option<u64>,
+    tick_rate: u32,
+    countdown_ticks: u64,
+    win_condition: WinCondition,
+    starting_gold: u32,
+    // gold a second, paid out a tick at a time while the battle runs
+    income: u32,
+}
+
+// one battle, completely independent of every other session on the server
+struct Match {
+    world: World,
+    rules: MatchRules,
+    catalogue: Arc<Catalogue>,
+    // chosen by the host, every rematch is fought on it too
+    map: Arc<Map>,
+    // what has happened in the current battle, and how many battles came before it
+    replay: Replay,
+    battle: u32,
+    // finished battles waiting to be written out
+    unsaved: Vec<(u32, Replay)>,
+    // counts every tick of the session, unlike the world's tick it carries on through a rematch
+    frame: u64,
+    phase: MatchPhase,
+    countdown_until: u64,
+    // how long the current battle has been running, income is worked out from it
+    running_ticks: u64,
+    next_player: u32,
+    clients: Vec<Client>,
+    // lifecycle changes waiting to be written to the log
+    events: Vec<String>,
+    // and what happened on the field
+    combat_log: TextLog,
+}
+
+struct Client {
+    player: u32,
+    name: String,
+    team: Team,
+    ready: bool,
+    gold: u32,
+    // what the client was last told it has, None until it has been told anything
+    gold_sent: Option<u32>,
+    outgoing: Outbox,
+}
+
+#[derive(Default)]
+struct ServerState {
+    rules: MatchRules,
+    catalogue: Arc<Catalogue>,
+    maps: Vec<Arc<Map>>,
+    sessions: HashMap<String, Match>,
+}
+
+type SharedState = Arc<Mutex<ServerState>>;
+
+enum Outgoing {
+    Reply(ServerMessage),
+    // the same snapshot is shared by every client, each writer turns it into its own delta
+    Tick(u64, Arc<Vec<Character>>, Arc<Map>),
+}
+
+// one client's side of its writer's queue, shared by the connection and the match it plays in
+#[derive(Clone)]
+struct Outbox {
+    queue: UnboundedSender<Outgoing>,
+    // everything sent that the writer has not got round to yet
+    queued: Arc<AtomicUsize>,
+}
+
+impl Outbox {
+    fn new() -> (Outbox, UnboundedReceiver<Outgoing>) {
+        let (queue, receiver) = unbounded();
+        let outbox = Outbox {
+            queue,
+            queued: Arc::new(AtomicUsize::new(0)),
+        };
+        (outbox, receiver)
+    }
+
+    // replies, phase changes, chat and events always get through, Err once the client is gone
+    fn reply(&self, message: ServerMessage) -> Result<(), ()> {
+        if self.queued.load(Ordering::Relaxed) >= CLIENT_BACKLOG_LEN {
+            self.close();
+            return Err(());
+        }
+        self.send(Outgoing::Reply(message))
+    }
+
+    // a client that is behind simply misses this tick, the next one supersedes it anyway
+    fn tick(&self, tick: u64, pieces: Arc<Vec<Character>>, map: Arc<Map>) -> Result<(), ()> {
+        if self.queue.is_closed() {
+            return Err(());
+        }
+        if self.queued.load(Ordering::Relaxed) >= CLIENT_QUEUE_LEN {
+            return Ok(());
+        }
+        self.send(Outgoing::Tick(tick, pieces, map))
+    }
 
-pub async fn server(listener: TcpListener) {
-    let mut pieces: Vec<Character> = Vec::new();
+    fn send(&self, outgoing: Outgoing) -> Result<(), ()> {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.queue.unbounded_send(outgoing).map_err(|_| ())
+    }
 
+    fn close(&self) {
+        self.queue.close_channel();
+    }
+}
+
+// what the reader side of one connection knows about itself
+struct Connection {
+    session: Option<String>,
+    player: Option<u32>,
+    team: Option<Team>,
+    outgoing: Outbox,
+    acked: Arc<AtomicU64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    // simulation steps per second
+    pub tick_rate: u32,
+    // after a stall at most this many overdue steps are run back to back, the rest are skipped
+    pub max_catch_up: u32,
+    // fixes the rng seed of every match instead of picking a fresh one per session
+    pub seed: Option<u64>,
+    // how long the battle waits once every player is ready
+    pub countdown_secs: u32,
+    pub win_condition: WinCondition,
+    // what every player has to spend when a battle starts, and earns a second while it runs
+    pub starting_gold: u32,
+    pub income: u32,
+    // the units players may field, sent to every client as it joins
+    pub catalogue: Catalogue,
+    // the maps a host can pick from, the first is used when they don't
+    pub maps: Vec<Map>,
+    // every battle is saved here as a replay when it ends, nothing is recorded when unset
+    pub replay_dir: Option<PathBuf>,
+    pub log_path: PathBuf,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            tick_rate: 100,
+            max_catch_up: 5,
+            seed: None,
+            countdown_secs: 3,
+            win_condition: WinCondition::LastTeamStanding,
+            starting_gold: 300,
+            income: 50,
+            catalogue: Catalogue::built_in().clone(),
+            maps: vec![Map::built_in().clone()],
+            replay_dir: None,
+            log_path: PathBuf::from("logging.txt"),
+        }
+    }
+}
+
+pub async fn server(listener: TcpListener, config: ServerConfig) {
+    let tick_rate = config.tick_rate.max(1);
+    let rules = MatchRules {
+        seed: config.seed,
+        tick_rate,
+        countdown_ticks: config.countdown_secs as u64 * tick_rate as u64,
+        win_condition: config.win_condition,
+        starting_gold: config.starting_gold,
+        income: config.income,
+    };
+    let state: SharedState = Arc::new(Mutex::new(ServerState {
+        rules,
+        catalogue: Arc::new(config.catalogue),
+        maps: if config.maps.is_empty() {
+            vec![Arc::new(Map::built_in().clone())]
+        } else {
+            config.maps.into_iter().map(Arc::new).collect()
+        },
+        ..ServerState::default()
+    }));
+    let log_path = Arc::new(config.log_path);
+    let replay_dir = config.replay_dir;
+    let tick_length = Duration::from_secs(1) / tick_rate;
+
+    spawn(accept_connections(
+        listener,
+        state.clone(),
+        log_path.clone(),
+    ));
+
+    let mut timestep = FixedTimestep::new(tick_length, Instant::now(), config.max_catch_up);
     loop {
-        let mut delay = Delay::new(Duration::from_millis(10)).fuse();
-        let nla_event = listener.accept().fuse();
-        pin_mut!(nla_event);
-
-        select! {
-            _ = delay => {
-                if pieces.len() > 1 {
-                    update_movement(&mut pieces);
-                    update_attacks(&mut pieces);
+        Delay::new(
+            timestep
+                .next_tick()
+                .saturating_duration_since(Instant::now()),
+        )
+        .await;
+
+        let due = timestep.due(Instant::now());
+        let mut log = Vec::new();
+        let mut replays = Vec::new();
+        {
+            let mut state = state.lock().unwrap();
+            for _ in 0..due {
+                for (code, game) in state.sessions.iter_mut() {
+                    game.step();
+                    log.extend(game.combat_log.take());
+                    log.extend(game.events.drain(..).map(|e| format!("session {code} {e}")));
+                    replays.extend(
+                        game.unsaved
+                            .drain(..)
+                            .map(|(battle, replay)| (format!("{code}-{battle}"), replay)),
+                    );
                 }
-            },
-            nla_handler = nla_event => {
-                let stream = match nla_handler {
-                    Ok(e) => e.0,
-                    Err(_) => continue,
-                };
-                handle_connection(stream, &mut pieces).await;
-            },
+            }
+
+            // once everyone has left a session there is nobody to ever rejoin it by code,
+            // a battle cut short that way is still worth keeping
+            state.sessions.retain(|code, game| {
+                if !game.clients.is_empty() {
+                    return true;
+                }
+                game.record_events();
+                if !matches!(game.phase, MatchPhase::Finished(_)) && !game.replay.spawns.is_empty()
+                {
+                    replays.push((format!("{code}-{}", game.battle), game.replay_so_far()));
+                }
+                false
+            });
+        }
 
+        // files are written once per tick, after the lock is released
+        if let Some(dir) = &replay_dir {
+            for (name, replay) in replays {
+                log.push(save_replay(dir, &name, &replay).await);
+            }
+        }
+        if !log.is_empty() {
+            logging(&log_path, log.join("\n")).await;
         }
     }
 }
 
-async fn handle_connection(mut stream: TcpStream, pieces: &mut Vec<Character>) {
-    let mut buffer = vec![0; 1024];
+impl MatchRules {
+    fn world(&self, map: &Map) -> World {
+        let mut world = World::with_seed(self.seed.unwrap_or_else(|| fastrand::u64(..)));
+        world.set_terrain(map.tiles.clone());
+        world
+    }
 
-    let size = match stream.read(&mut buffer).await {
-        Ok(x) => x,
-        Err(x) => {
-            logging(format!("👂 Err Read {:?}", x)).await;
-            0
+    fn replay(&self, world: &World, catalogue: &Catalogue, map: &Map) -> Replay {
+        Replay::new(
+            world.seed().unwrap_or_default(),
+            self.tick_rate,
+            self.win_condition,
+            catalogue.clone(),
+            map.clone(),
+        )
+    }
+}
+
+impl Match {
+    fn new(rules: MatchRules, catalogue: Arc<Catalogue>, map: Arc<Map>) -> Match {
+        let world = rules.world(&map);
+        let replay = rules.replay(&world, &catalogue, &map);
+        Match {
+            world,
+            rules,
+            catalogue,
+            map,
+            replay,
+            battle: 0,
+            unsaved: Vec::new(),
+            frame: 0,
+            phase: MatchPhase::Lobby,
+            countdown_until: 0,
+            running_ticks: 0,
+            next_player: 0,
+            clients: Vec::new(),
+            events: Vec::new(),
+            combat_log: TextLog::default(),
         }
-    };
+    }
 
-    let request = match String::from_utf8(buffer[..size].to_vec()) {
-        Ok(r) => r,
-        Err(_) => "".to_string(),
-    };
+    fn step(&mut self) {
+        self.frame += 1;
 
-    let mut response: Vec<u8> = Vec::new();
-
-    let request_str = request.as_str();
-    if request_str == "new game" {
-        let id = format!("{:x}", fastrand::u128(..));
-        println!("{id}");
-        response = id.into_bytes();
-    } else if request_str == "update" {
-        response = bincode::serialize(&pieces).unwrap();
-    } else if request_str.len() == 3 {
-        let mut request_bytes = request_str.bytes();
-        let col = request_bytes.next().unwrap();
-        let chr = request_bytes.next().unwrap();
-        let y: i16 = (request_bytes.next().unwrap() as i16 - 48) * 2;
-        let col = if col == b'r' {
-            Color::Red
-        } else {
-            Color::Green
-        };
+        // spawns land between steps, their events go out before the next one whatever the phase
+        self.record_events();
 
-        match chr {
-            b'g' => {
-                pieces.push(generate_giant(y as i16, col));
+        // the battle itself only moves while running, before and after it the field stays as it is
+        match self.phase {
+            MatchPhase::Lobby => {
+                if !self.clients.is_empty() && self.clients.iter().all(|c| c.ready) {
+                    self.countdown_until = self.frame + self.rules.countdown_ticks;
+                    self.enter(MatchPhase::Countdown {
+                        seconds_left: self.seconds_left(),
+                    });
+                }
             }
-            b'b' => {
-                pieces.push(generate_barbarian(y as i16, col));
+            MatchPhase::Countdown { seconds_left } => {
+                if self.frame >= self.countdown_until {
+                    self.enter(MatchPhase::Running);
+                } else if self.seconds_left() != seconds_left {
+                    self.enter(MatchPhase::Countdown {
+                        seconds_left: self.seconds_left(),
+                    });
+                }
             }
-            b'a' => {
-                pieces.push(generate_archer(y as i16, col));
+            MatchPhase::Running => {
+                self.world.step();
+                self.record_events();
+                self.pay_income();
+                if let Some(outcome) = self.world.outcome(self.rules.win_condition) {
+                    self.enter(MatchPhase::Finished(outcome));
+                }
             }
-            _ => (),
+            MatchPhase::Finished(_) => (),
         }
+
+        self.send_gold();
+
+        let frame = self.frame;
+        let snapshot = Arc::new(self.world.pieces().to_vec());
+        let map = &self.map;
+        self.clients.retain(|client| {
+            client
+                .outgoing
+                .tick(frame, snapshot.clone(), map.clone())
+                .is_ok()
+        });
     }
 
-    if !response.is_empty() {
-        stream.write_all(&response[..]).await.unwrap();
-        match stream.flush().await {
-            Ok(_) => (),
-            Err(x) => {
-                logging(format!("👂 Err Write {:?}", x)).await;
+    // the log, the replay and the players all follow the battle from the same events
+    fn record_events(&mut self) {
+        let events = self.world.take_events();
+        if events.is_empty() {
+            return;
+        }
+        let tick = self.world.tick();
+        self.combat_log.record(tick, &events);
+        self.replay.record(tick, &events);
+
+        // movement stays on the server, every snapshot already shows where everyone went
+        let events: Vec<CombatEvent> = events
+            .into_iter()
+            .filter(|e| e.kind() != EventKind::Moved)
+            .collect();
+        if !events.is_empty() {
+            self.broadcast(ServerMessage::Events { tick, events });
+        }
+    }
+
+    // whole gold pieces only, what a tick earns is what the running total has grown by since the last one
+    fn pay_income(&mut self) {
+        let rate = self.rules.tick_rate.max(1) as u64;
+        let earned = |ticks: u64| ticks * self.rules.income as u64 / rate;
+        let pay = earned(self.running_ticks + 1) - earned(self.running_ticks);
+        self.running_ticks += 1;
+        for client in &mut self.clients {
+            client.gold = client.gold.saturating_add(pay as u32);
+        }
+    }
+
+    // at most ten times a second while income trickles in, straight away otherwise
+    fn send_gold(&mut self) {
+        let every = (self.rules.tick_rate / 10).max(1) as u64;
+        if self.phase == MatchPhase::Running && !self.frame.is_multiple_of(every) {
+            return;
+        }
+        let income = self.rules.income;
+        for client in &mut self.clients {
+            if client.gold_sent == Some(client.gold) {
+                continue;
+            }
+            let gold = ServerMessage::Gold {
+                gold: client.gold,
+                income,
+            };
+            if client.outgoing.reply(gold).is_ok() {
+                client.gold_sent = Some(client.gold);
             }
         }
     }
-}
 
-fn calc_distance(x1: i32, y1: i32, x2: i32, y2: i32) -> f32 {
-    if y1 == y2 {
-        return 0.0001;
-    };
+    fn seconds_left(&self) -> u32 {
+        let ticks = self.countdown_until.saturating_sub(self.frame);
+        ticks.div_ceil(self.rules.tick_rate.max(1) as u64) as u32
+    }
+
+    fn enter(&mut self, phase: MatchPhase) {
+        if let MatchPhase::Finished(_) = phase {
+            self.unsaved.push((self.battle, self.replay_so_far()));
+        }
+        self.phase = phase;
+        self.events.push(format!("{:?}", phase));
+        self.broadcast(ServerMessage::Phase(phase));
+    }
+
+    // a fresh battle for the same players, who all have to ready up again
+    fn rematch(&mut self) {
+        self.world = self.rules.world(&self.map);
+        self.replay = self.rules.replay(&self.world, &self.catalogue, &self.map);
+        self.battle += 1;
+        self.running_ticks = 0;
+        for client in &mut self.clients {
+            client.ready = false;
+            client.gold = self.rules.starting_gold;
+        }
+        self.events.push(format!(
+            "rematch with seed {}",
+            self.world.seed().unwrap_or_default()
+        ));
+        self.enter(MatchPhase::Lobby);
+    }
+
+    fn replay_so_far(&self) -> Replay {
+        Replay {
+            length: self.world.tick(),
+            ..self.replay.clone()
+        }
+    }
 
-    // √[(x₂ - x₁)² + (y₂ - y₁)²]
-    let x = x2 - x1;
-    let x = x.pow(2) as f32;
-    let y = y2 - y1;
-    let y = y.pow(2) as f32;
+    // unlike ticks these are never skipped, a client that has gone is dropped with the next tick
+    fn broadcast(&mut self, message: ServerMessage) {
+        for client in &mut self.clients {
+            let _ = client.outgoing.reply(message.clone());
+        }
+    }
 
-    (x + y).sqrt()
+    fn client(&mut self, player: Option<u32>) -> Option<&mut Client> {
+        self.clients.iter_mut().find(|c| Some(c.player) == player)
+    }
 }
 
-fn update_movement(pieces: &mut Vec<Character>) {
-    let mut ids: Vec<usize> = (0..pieces.len()).collect();
-    fastrand::shuffle(&mut ids);
+async fn accept_connections(listener: TcpListener, state: SharedState, log_path: Arc<PathBuf>) {
+    let mut incoming = listener.incoming();
 
-    for i in ids {
-        if pieces[i].hp < 1 {
-            continue;
+    while let Some(stream) = incoming.next().await {
+        match stream {
+            Ok(stream) => {
+                spawn(handle_connection(stream, state.clone(), log_path.clone()));
+            }
+            Err(x) => logging(&log_path, format!("👂 Err Accept {:?}", x)).await,
         }
+    }
+}
 
-        // need to play test if letting movement cooldown continue during attacking or not has a positive/negative effect on play
-        if pieces[i].is_attacking {
-            continue;
-        }
+async fn handle_connection(stream: TcpStream, state: SharedState, log_path: Arc<PathBuf>) {
+    let (outgoing, queue) = Outbox::new();
 
-        pieces[i].movement_cooldown -= 1;
-
-        if pieces[i].movement_cooldown <= 0 {
-            // find the shortest distance to the nearest enemy
-            let mut shortest_distance: f32 = 99999.999;
-            let mut closest_enemy: usize = pieces.len();
-
-            for j in 0..pieces.len() {
-                // check that the item is not an enemy
-                if pieces[i].color != pieces[j].color && pieces[j].hp > 0 {
-                    let dist = calc_distance(
-                        pieces[i].x as i32,
-                        pieces[i].y as i32,
-                        pieces[j].x as i32,
-                        pieces[j].y as i32,
-                    );
-                    if dist < shortest_distance {
-                        shortest_distance = dist;
-                        closest_enemy = j;
-                    }
+    let mut connection = Connection {
+        session: None,
+        player: None,
+        team: None,
+        outgoing,
+        // 0 means nothing acknowledged yet, ticks start counting from 1
+        acked: Arc::new(AtomicU64::new(0)),
+    };
+    spawn(write_messages(
+        stream.clone(),
+        queue,
+        connection.outgoing.queued.clone(),
+        connection.acked.clone(),
+        log_path.clone(),
+    ));
+
+    let mut reader = stream;
+    loop {
+        let request = match read_frame(&mut reader).await {
+            Ok(r) => r,
+            Err(x) => {
+                if x.kind() != ErrorKind::UnexpectedEof {
+                    logging(&log_path, format!("👂 Err Read {:?}", x)).await;
                 }
+                break;
             }
+        };
 
-            if closest_enemy < pieces.len() {
-                let mut movex = 0;
-                let mut movey = 0;
+        // a frame that does not decode leaves the stream in sync, so answer it and carry on
+        let response = match bincode::deserialize::<ClientMessage>(&request) {
+            Ok(request) => handle_message(request, &state, &mut connection),
+            Err(x) => Some(ServerMessage::Error(ServerError::Malformed(x.to_string()))),
+        };
 
-                // enemy located
-                if pieces[i].x < pieces[closest_enemy].x {
-                    movex = 1_i16;
-                }
-                if pieces[i].x > pieces[closest_enemy].x {
-                    movex = -1_i16;
-                }
-                if pieces[i].y < pieces[closest_enemy].y {
-                    movey = 1_i16;
-                }
-                if pieces[i].y > pieces[closest_enemy].y {
-                    movey = -1_i16;
-                }
+        // the seed is all it takes to reproduce a match, so keep a record of it here and not with the players
+        if let Some(ServerMessage::GameCreated { code, map, .. }) = &response {
+            let seed = state
+                .lock()
+                .unwrap()
+                .sessions
+                .get(code)
+                .and_then(|game| game.world.seed())
+                .unwrap_or_default();
+            logging(
+                &log_path,
+                format!("session {code} created on {} with seed {seed}", map.name),
+            )
+            .await;
+        }
 
-                let mut valid_move = true;
-                for j in 0..pieces.len() {
-                    if i == j {
-                        continue;
-                    } // ignore self
-                    if pieces[j].hp <= 0 {
-                        continue;
-                    } // ignore dead
-
-                    if pieces[i].x + movex == pieces[j].x && pieces[i].y + movey == pieces[j].y {
-                        valid_move = false;
-                        break;
-                    }
-                }
-                if valid_move {
-                    pieces[i].x += movex;
-                    pieces[i].y += movey;
-                    pieces[i].movement_cooldown = pieces[i].movement_rate;
-                }
+        if let Some(response) = response {
+            if connection.outgoing.reply(response).is_err() {
+                break;
             }
         }
     }
+
+    // closing the channel ends the writer and lets the tick loop forget this client
+    connection.outgoing.close();
 }
 
-fn update_attacks(pieces: &mut Vec<Character>) {
-    let mut ids: Vec<usize> = (0..pieces.len()).collect();
-    fastrand::shuffle(&mut ids);
+async fn write_messages(
+    mut writer: TcpStream,
+    mut queue: UnboundedReceiver<Outgoing>,
+    queued: Arc<AtomicUsize>,
+    acked: Arc<AtomicU64>,
+    log_path: Arc<PathBuf>,
+) {
+    let mut encoder = DeltaEncoder::default();
+
+    while let Some(outgoing) = queue.next().await {
+        queued.fetch_sub(1, Ordering::Relaxed);
+        let message = match outgoing {
+            Outgoing::Reply(message) => message,
+            Outgoing::Tick(tick, pieces, map) => {
+                let acked = Some(acked.load(Ordering::Relaxed)).filter(|t| *t > 0);
+                encoder.encode(acked, tick, pieces, &map.tiles)
+            }
+        };
 
-    for i in ids {
-        if pieces[i].hp < 1 {
-            continue;
+        if let Err(x) = send_message(&mut writer, &message).await {
+            logging(&log_path, format!("👂 Err Write {:?}", x)).await;
+            break;
         }
+    }
+}
 
-        if pieces[i].attack_cooldown > 0 {
-            pieces[i].attack_cooldown -= 1;
-        } else {
-            //block_on(logging(format!("{}{:0x} ready to attack", pieces[i].denotation, pieces[i].unique_id)));
-            pieces[i].is_attacking = false;
-
-            for j in 0..pieces.len() {
-                // check that the item is not an enemy and is alive
-                if pieces[i].color != pieces[j].color && pieces[j].hp > 0 {
-                    // check that the items are in range of each other for effect
-                    if pieces[i].x >= pieces[j].x - pieces[i].attack_range
-                        && pieces[i].x <= pieces[j].x + pieces[i].attack_range
-                        && pieces[i].y >= pieces[j].y - pieces[i].attack_range
-                        && pieces[i].y <= pieces[j].y + pieces[i].attack_range
-                    {
-                        block_on(logging(format!(
-                            "{}{:0x} will attack {}{:0x}",
-                            pieces[i].denotation,
-                            pieces[i].unique_id,
-                            pieces[j].denotation,
-                            pieces[j].unique_id
-                        )));
-
-                        // pause moving while attacking - glass cannons don't want to be walking to their death
-                        pieces[i].is_attacking = true;
-
-                        // attack rolls
-                        // 2d6 + attack_skill > enemy defence_class
-
-                        let attack_roll =
-                            fastrand::i16(1..7) + fastrand::i16(1..7) + pieces[i].attack_skill;
-                        block_on(logging(format!(
-                            "{}{:0x} rolled to attack: {} vs enemy defence: {}",
-                            pieces[i].denotation,
-                            pieces[i].unique_id,
-                            attack_roll,
-                            pieces[j].defence_class
-                        )));
-
-                        if attack_roll >= pieces[j].defence_class {
-                            block_on(logging(format!(
-                                "{}{:0x} passed attack roll",
-                                pieces[i].denotation, pieces[i].unique_id
-                            )));
-
-                            // passed check, do damage
-                            let damage = fastrand::i16(
-                                pieces[i].damage_range.start..pieces[i].damage_range.end,
-                            );
-                            pieces[j].hp -= damage;
-
-                            block_on(logging(format!(
-                                "{}{:0x} causes {} damage, leaving {} hp",
-                                pieces[i].denotation, pieces[i].unique_id, damage, pieces[j].hp
-                            )));
-
-                            if pieces[j].hp <= 0 {
-                                pieces[i].is_attacking = false;
-                                block_on(logging(format!(
-                                    "{}{:0x} defeated enemy",
-                                    pieces[i].denotation, pieces[i].unique_id
-                                )));
+fn handle_message(
+    request: ClientMessage,
+    state: &SharedState,
+    connection: &mut Connection,
+) -> Option<ServerMessage> {
+    let mut state = state.lock().unwrap();
+
+    let reply = match request {
+        // a connection stays with one session for its lifetime, tick numbers are only unique within a match
+        ClientMessage::NewGame { .. } | ClientMessage::JoinGame { .. }
+            if connection.session.is_some() =>
+        {
+            ServerMessage::Error(ServerError::AlreadyInSession)
+        }
+        ClientMessage::NewGame { map } => {
+            let map = match map {
+                None => state.maps[0].clone(),
+                Some(name) => match state.maps.iter().find(|m| m.name == name) {
+                    Some(map) => map.clone(),
+                    None => {
+                        let available = state.maps.iter().map(|m| m.name.clone()).collect();
+                        return Some(ServerMessage::Error(ServerError::UnknownMap {
+                            name,
+                            available,
+                        }));
+                    }
+                },
+            };
+            let code = loop {
+                let code = format!("{:x}", fastrand::u128(..));
+                if !state.sessions.contains_key(&code) {
+                    break code;
+                }
+            };
+            let game = Match::new(state.rules, state.catalogue.clone(), map.clone());
+            state.sessions.insert(code.clone(), game);
+            let (player, team) = attach(&mut state, connection, &code);
+            ServerMessage::GameCreated {
+                code,
+                player,
+                team,
+                phase: MatchPhase::Lobby,
+                catalogue: state.catalogue.as_ref().clone(),
+                map: map.as_ref().clone(),
+            }
+        }
+        ClientMessage::JoinGame { code } => {
+            if state.sessions.contains_key(&code) {
+                let (player, team) = attach(&mut state, connection, &code);
+                let game = &state.sessions[&code];
+                ServerMessage::GameJoined {
+                    phase: game.phase,
+                    map: game.map.as_ref().clone(),
+                    code,
+                    player,
+                    team,
+                    catalogue: state.catalogue.as_ref().clone(),
+                }
+            } else {
+                ServerMessage::Error(ServerError::UnknownSession(code))
+            }
+        }
+        ClientMessage::Acknowledge { tick } => {
+            connection.acked.store(tick, Ordering::Relaxed);
+            return None;
+        }
+        ClientMessage::RequestSnapshot => match current_match(&mut state, connection) {
+            Some(game) => ServerMessage::Snapshot {
+                tick: game.frame,
+                pieces: game.world.pieces().to_vec(),
+                terrain: game.world.terrain().clone(),
+            },
+            None => ServerMessage::Error(ServerError::NotInSession),
+        },
+        // everything a spawn has to pass before it reaches the world, in this order
+        ClientMessage::SpawnUnit { unit, row } => {
+            let (team, player) = (connection.team, connection.player);
+            // the unit always fights for the side the server put this connection on
+            match current_match(&mut state, connection).zip(team) {
+                None => ServerMessage::Error(ServerError::NotInSession),
+                Some((game, _)) if matches!(game.phase, MatchPhase::Finished(_)) => {
+                    ServerMessage::Error(ServerError::WrongPhase(game.phase))
+                }
+                Some((game, team)) => match game.map.spawn_point(team, row) {
+                    None => ServerMessage::Error(ServerError::RowOutOfRange(row)),
+                    Some((x, y)) => match game.catalogue.clone().unit(&unit) {
+                        Some(unit) => {
+                            let gold = game.client(player).map_or(0, |c| c.gold);
+                            if gold < unit.cost {
+                                return Some(ServerMessage::Error(ServerError::NotEnoughGold {
+                                    cost: unit.cost,
+                                    gold,
+                                }));
+                            }
+                            if game.world.spawn(generate_unit(unit, x, y, team)).is_none() {
+                                return Some(ServerMessage::Error(ServerError::FieldFull));
+                            }
+                            if let Some(client) = game.client(player) {
+                                client.gold -= unit.cost;
                             }
+                            ServerMessage::SpawnAccepted
                         }
-
-                        pieces[i].attack_cooldown = pieces[i].attack_rate;
+                        None => ServerMessage::Error(ServerError::UnknownUnit(unit)),
+                    },
+                },
+            }
+        }
+        ClientMessage::Ready => {
+            let player = connection.player;
+            match current_match(&mut state, connection) {
+                Some(game) if game.phase == MatchPhase::Lobby => {
+                    if let Some(client) = game.client(player) {
+                        client.ready = true;
+                    }
+                    return None;
+                }
+                Some(game) => ServerMessage::Error(ServerError::WrongPhase(game.phase)),
+                None => ServerMessage::Error(ServerError::NotInSession),
+            }
+        }
+        ClientMessage::Rematch => match current_match(&mut state, connection) {
+            Some(game) if matches!(game.phase, MatchPhase::Finished(_)) => {
+                game.rematch();
+                return None;
+            }
+            Some(game) => ServerMessage::Error(ServerError::WrongPhase(game.phase)),
+            None => ServerMessage::Error(ServerError::NotInSession),
+        },
+        ClientMessage::SetName { name } => {
+            let player = connection.player;
+            match current_match(&mut state, connection).and_then(|game| game.client(player)) {
+                Some(client) => {
+                    let name = clean_text(&name, MAX_NAME_LEN);
+                    if !name.is_empty() {
+                        client.name = name;
+                    }
+                    return None;
+                }
+                None => ServerMessage::Error(ServerError::NotInSession),
+            }
+        }
+        ClientMessage::Chat { text } => {
+            let player = connection.player;
+            match current_match(&mut state, connection) {
+                Some(game) => {
+                    let text = clean_text(&text, MAX_CHAT_LEN);
+                    if let Some(client) = game.client(player).filter(|_| !text.is_empty()) {
+                        let line = ServerMessage::Chat {
+                            player: client.player,
+                            name: client.name.clone(),
+                            team: client.team,
+                            text,
+                        };
+                        game.broadcast(line);
                     }
+                    return None;
                 }
+                None => ServerMessage::Error(ServerError::NotInSession),
             }
         }
+    };
+
+    Some(reply)
+}
+
+// joins the connection to a session as a new player on whichever side is short handed
+fn attach(state: &mut ServerState, connection: &mut Connection, code: &str) -> (u32, Team) {
+    let game = state.sessions.get_mut(code).unwrap();
+
+    let greens = game
+        .clients
+        .iter()
+        .filter(|c| c.team == Team::Green)
+        .count();
+    let reds = game.clients.len() - greens;
+    let team = if reds < greens {
+        Team::Red
+    } else {
+        Team::Green
+    };
+
+    let player = game.next_player;
+    game.next_player += 1;
+    game.clients.push(Client {
+        player,
+        name: format!("Player {player}"),
+        team,
+        ready: false,
+        gold: game.rules.starting_gold,
+        gold_sent: None,
+        outgoing: connection.outgoing.clone(),
+    });
+
+    connection.session = Some(code.to_string());
+    connection.player = Some(player);
+    connection.team = Some(team);
+    (player, team)
+}
+
+fn current_match<'a>(state: &'a mut ServerState, connection: &Connection) -> Option<&'a mut Match> {
+    connection
+        .session
+        .as_ref()
+        .and_then(|code| state.sessions.get_mut(code))
+}
+
+// the line to log about it, whether it worked or not
+async fn save_replay(dir: &Path, name: &str, replay: &Replay) -> String {
+    let path = dir.join(format!("{name}.ron"));
+    let saved = match async_std::fs::create_dir_all(dir).await {
+        Ok(_) => async_std::fs::write(&path, replay.to_ron()).await,
+        Err(e) => Err(e),
+    };
+
+    match saved {
+        Ok(_) => format!("replay saved to {}", path.display()),
+        Err(e) => format!("👂 Err Replay {} {:?}", path.display(), e),
     }
 }
 
-async fn logging(s: String) {
-    let mut file = async_std::fs::OpenOptions::new()
+// control characters would let one player redraw everyone else's terminal
+fn clean_text(text: &str, max_len: usize) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .take(max_len)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// a log that cannot be opened is no reason to stop the battles, the lines go to stderr instead
+async fn logging(path: &Path, s: String) {
+    let now = now();
+    let log: String = s.lines().map(|line| format!("{now}  {line}\n")).collect();
+    let file = async_std::fs::OpenOptions::new()
         .write(true)
         .create(true)
         .append(true)
-        .open("logging.txt")
-        .await
-        .unwrap();
-    //write!(&mut file, s);
-    let log = format!("{}  {s}\n", now());
-    let _ = AsyncWriteExt::write_all(&mut file, log.as_bytes()).await;
+        .open(path)
+        .await;
+    match file {
+        Ok(mut file) => {
+            let _ = AsyncWriteExt::write_all(&mut file, log.as_bytes()).await;
+        }
+        Err(e) => eprint!("could not open {}: {e}\n{log}", path.display()),
+    }
 }
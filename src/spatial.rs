@@ -0,0 +1,202 @@
+use crate::*;
+
+// the living pieces bucketed by the cell they stand in, by their position in the world's pieces
+pub struct SpatialIndex {
+    width: i16,
+    height: i16,
+    cells: Vec<Vec<usize>>,
+    // anything put down off the field still has to be found, it just gets no bucket
+    outside: Vec<usize>,
+    // how many of each side are in here, so a search with no enemies left can stop straight away
+    teams: Vec<(Team, usize)>,
+}
+
+impl SpatialIndex {
+    pub fn new(width: i16, height: i16) -> SpatialIndex {
+        SpatialIndex {
+            width,
+            height,
+            cells: vec![Vec::new(); width.max(0) as usize * height.max(0) as usize],
+            outside: Vec::new(),
+            teams: Vec::new(),
+        }
+    }
+
+    pub fn build(pieces: &[Character], width: i16, height: i16) -> SpatialIndex {
+        let mut index = SpatialIndex::new(width, height);
+        for (i, p) in pieces.iter().enumerate().filter(|(_, p)| p.hp > 0) {
+            index.insert(i, p);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, i: usize, piece: &Character) {
+        self.bucket(piece.x, piece.y).push(i);
+        match self.teams.iter_mut().find(|(team, _)| *team == piece.team) {
+            Some((_, count)) => *count += 1,
+            None => self.teams.push((piece.team, 1)),
+        }
+    }
+
+    pub fn remove(&mut self, i: usize, piece: &Character) {
+        if self.take(i, piece.x, piece.y) {
+            if let Some((_, count)) = self.teams.iter_mut().find(|(team, _)| *team == piece.team) {
+                *count -= 1;
+            }
+        }
+    }
+
+    pub fn relocate(&mut self, i: usize, from: (i16, i16), to: (i16, i16)) {
+        if self.take(i, from.0, from.1) {
+            self.bucket(to.0, to.1).push(i);
+        }
+    }
+
+    pub fn at(&self, x: i16, y: i16) -> &[usize] {
+        if self.contains(x, y) {
+            &self.cells[self.cell(x, y)]
+        } else {
+            &[]
+        }
+    }
+
+    // only cells on the field can be stood in
+    pub fn occupied(&self, x: i16, y: i16) -> bool {
+        !self.at(x, y).is_empty()
+    }
+
+    // everyone within range on both axes, in the order they sit in the pieces
+    pub fn within(&self, pieces: &[Character], x: i16, y: i16, range: i16) -> Vec<usize> {
+        let mut found = Vec::new();
+        for cy in (y - range).max(0)..=(y + range).min(self.height - 1) {
+            for cx in (x - range).max(0)..=(x + range).min(self.width - 1) {
+                found.extend_from_slice(self.at(cx, cy));
+            }
+        }
+        found.extend(
+            self.outside
+                .iter()
+                .copied()
+                .filter(|&j| (pieces[j].x - x).abs() <= range && (pieces[j].y - y).abs() <= range),
+        );
+        found.sort_unstable();
+        found
+    }
+
+    // the enemy closest to piece i, ties going to whoever comes first in the pieces
+    pub fn nearest_enemy(&self, pieces: &[Character], i: usize) -> Option<usize> {
+        let (x, y) = (pieces[i].x, pieces[i].y);
+        if !self
+            .teams
+            .iter()
+            .any(|(team, count)| *team != pieces[i].team && *count > 0)
+        {
+            return None;
+        }
+
+        let enemy = |j: &usize| pieces[*j].team != pieces[i].team;
+        let mut best: Option<(f32, usize)> = None;
+        let consider = |best: &mut Option<(f32, usize)>, j: usize| {
+            let d = distance(x, y, pieces[j].x, pieces[j].y);
+            let closer = match *best {
+                None => true,
+                Some((bd, bj)) => d < bd || (d == bd && j < bj),
+            };
+            if closer {
+                *best = Some((d, j));
+            }
+        };
+
+        // anyone on the same row counts as right next to us, so look there first
+        if (0..self.height).contains(&y) {
+            for cx in 0..self.width {
+                self.at(cx, y)
+                    .iter()
+                    .copied()
+                    .filter(enemy)
+                    .for_each(|j| consider(&mut best, j));
+            }
+        }
+        self.outside
+            .iter()
+            .copied()
+            .filter(enemy)
+            .for_each(|j| consider(&mut best, j));
+
+        // then rings outwards, until nothing further out could be nearer
+        let widest = x
+            .abs()
+            .max((self.width - 1 - x).abs())
+            .max(y.abs())
+            .max((self.height - 1 - y).abs());
+        let columns = |from: i16, to: i16| from.max(0)..=to.min(self.width - 1);
+        let rows = |from: i16, to: i16| from.max(0)..=to.min(self.height - 1);
+        for r in 1..=widest {
+            if best.is_some_and(|(d, _)| d < r as f32) {
+                break;
+            }
+            for cy in [y - r, y + r] {
+                for cx in columns(x - r, x + r) {
+                    self.at(cx, cy)
+                        .iter()
+                        .copied()
+                        .filter(enemy)
+                        .for_each(|j| consider(&mut best, j));
+                }
+            }
+            for cx in [x - r, x + r] {
+                for cy in rows(y - r + 1, y + r - 1) {
+                    self.at(cx, cy)
+                        .iter()
+                        .copied()
+                        .filter(enemy)
+                        .for_each(|j| consider(&mut best, j));
+                }
+            }
+        }
+
+        best.map(|(_, j)| j)
+    }
+
+    // false if i was not there to take
+    fn take(&mut self, i: usize, x: i16, y: i16) -> bool {
+        let bucket = self.bucket(x, y);
+        match bucket.iter().position(|&j| j == i) {
+            Some(at) => {
+                bucket.swap_remove(at);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn contains(&self, x: i16, y: i16) -> bool {
+        (0..self.width).contains(&x) && (0..self.height).contains(&y)
+    }
+
+    fn cell(&self, x: i16, y: i16) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    fn bucket(&mut self, x: i16, y: i16) -> &mut Vec<usize> {
+        if self.contains(x, y) {
+            let cell = self.cell(x, y);
+            &mut self.cells[cell]
+        } else {
+            &mut self.outside
+        }
+    }
+}
+
+// straight line distance, except that the same row counts as no distance at all
+pub fn distance(x1: i16, y1: i16, x2: i16, y2: i16) -> f32 {
+    if y1 == y2 {
+        return 0.0001;
+    };
+
+    // √[(x₂ - x₁)² + (y₂ - y₁)²]
+    let x = (x2 as i32 - x1 as i32).pow(2) as f32;
+    let y = (y2 as i32 - y1 as i32).pow(2) as f32;
+
+    (x + y).sqrt()
+}
@@ -0,0 +1,221 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tile {
+    #[default]
+    Open,
+    // nobody walks through and nobody shoots through
+    Wall,
+    // harder to land a blow on whoever is standing in it
+    Forest,
+    // nobody wades across, but arrows fly over
+    Water,
+    // quicker going for whoever is on it
+    Road,
+}
+
+impl Tile {
+    // how the tile is written in a terrain's rows
+    pub const CODES: [(Tile, char); 5] = [
+        (Tile::Open, '.'),
+        (Tile::Wall, '#'),
+        (Tile::Forest, '^'),
+        (Tile::Water, '~'),
+        (Tile::Road, '='),
+    ];
+
+    pub fn passable(self) -> bool {
+        !matches!(self, Tile::Wall | Tile::Water)
+    }
+
+    pub fn defence_bonus(self) -> i16 {
+        match self {
+            Tile::Forest => 2,
+            _ => 0,
+        }
+    }
+
+    // the wait before the next step for a unit that has just stepped onto this tile
+    pub fn movement_rate(self, rate: i16) -> i16 {
+        match self {
+            Tile::Road => (rate + 1) / 2,
+            _ => rate,
+        }
+    }
+
+    pub fn code(self) -> char {
+        Tile::CODES.iter().find(|(t, _)| *t == self).unwrap().1
+    }
+
+    pub fn from_code(code: char) -> Option<Tile> {
+        Tile::CODES
+            .iter()
+            .find(|(_, c)| *c == code)
+            .map(|(t, _)| *t)
+    }
+}
+
+// what lies under the pieces, one tile for every cell of the field
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct Terrain {
+    width: i16,
+    height: i16,
+    tiles: Vec<Tile>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TerrainError {
+    Empty,
+    // more rows or columns than a coordinate can reach
+    TooLarge,
+    // row, then how long it was, every row has to be as long as the first
+    RowLength(usize, usize),
+    UnknownTile(char),
+}
+
+impl fmt::Display for TerrainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TerrainError::Empty => write!(f, "terrain needs at least one tile"),
+            TerrainError::TooLarge => write!(f, "terrain is too large"),
+            TerrainError::RowLength(row, n) => {
+                write!(f, "terrain row {row} is {n} tiles long, unlike the first")
+            }
+            TerrainError::UnknownTile(c) => write!(f, "no terrain tile is written {c:?}"),
+        }
+    }
+}
+
+// the size of the classic field, maps can be any size
+pub const FIELD_WIDTH: i16 = 70;
+pub const FIELD_HEIGHT: i16 = 20;
+
+// the field as it has always been, nothing but open ground
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain::open(FIELD_WIDTH, FIELD_HEIGHT)
+    }
+}
+
+impl Terrain {
+    pub fn open(width: i16, height: i16) -> Terrain {
+        Terrain {
+            width,
+            height,
+            tiles: vec![Tile::Open; width.max(0) as usize * height.max(0) as usize],
+        }
+    }
+
+    pub fn width(&self) -> i16 {
+        self.width
+    }
+
+    pub fn height(&self) -> i16 {
+        self.height
+    }
+
+    pub fn contains(&self, x: i16, y: i16) -> bool {
+        (0..self.width).contains(&x) && (0..self.height).contains(&y)
+    }
+
+    // off the field counts as wall, there is no going there
+    pub fn tile(&self, x: i16, y: i16) -> Tile {
+        if self.contains(x, y) {
+            self.tiles[self.cell(x, y)]
+        } else {
+            Tile::Wall
+        }
+    }
+
+    pub fn set(&mut self, x: i16, y: i16, tile: Tile) {
+        if self.contains(x, y) {
+            let cell = self.cell(x, y);
+            self.tiles[cell] = tile;
+        }
+    }
+
+    pub fn passable(&self, x: i16, y: i16) -> bool {
+        self.tile(x, y).passable()
+    }
+
+    // false if a wall stands on any cell of the straight line between the two, the ends themselves don't count
+    pub fn line_of_sight(&self, from: (i16, i16), to: (i16, i16)) -> bool {
+        let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+        let (sx, sy) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+        let (mut x, mut y) = from;
+        let mut error = dx + dy;
+
+        loop {
+            let e2 = 2 * error;
+            if e2 >= dy {
+                error += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                error += dx;
+                y += sy;
+            }
+            if (x, y) == to {
+                return true;
+            }
+            if self.tile(x, y) == Tile::Wall {
+                return false;
+            }
+        }
+    }
+
+    pub fn rows(&self) -> Vec<String> {
+        self.tiles
+            .chunks(self.width as usize)
+            .map(|row| row.iter().map(|t| t.code()).collect())
+            .collect()
+    }
+
+    pub fn parse<S: AsRef<str>>(rows: &[S]) -> Result<Terrain, TerrainError> {
+        let width = rows.first().map_or(0, |row| row.as_ref().chars().count());
+        if width == 0 {
+            return Err(TerrainError::Empty);
+        }
+        if width > i16::MAX as usize || rows.len() > i16::MAX as usize {
+            return Err(TerrainError::TooLarge);
+        }
+
+        let mut tiles = Vec::with_capacity(width * rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            let length = row.as_ref().chars().count();
+            if length != width {
+                return Err(TerrainError::RowLength(y, length));
+            }
+            for c in row.as_ref().chars() {
+                tiles.push(Tile::from_code(c).ok_or(TerrainError::UnknownTile(c))?);
+            }
+        }
+        Ok(Terrain {
+            width: width as i16,
+            height: rows.len() as i16,
+            tiles,
+        })
+    }
+
+    fn cell(&self, x: i16, y: i16) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+// written out as rows of tile codes, it keeps replays readable and snapshots small
+impl TryFrom<Vec<String>> for Terrain {
+    type Error = TerrainError;
+
+    fn try_from(rows: Vec<String>) -> Result<Self, Self::Error> {
+        Terrain::parse(&rows)
+    }
+}
+
+impl From<Terrain> for Vec<String> {
+    fn from(terrain: Terrain) -> Self {
+        terrain.rows()
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::{Duration, Instant};
+
+// steps the simulation on a fixed grid of instants, however late the caller wakes up
+pub struct FixedTimestep {
+    tick_length: Duration,
+    next_tick: Instant,
+    max_catch_up: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_length: Duration, start: Instant, max_catch_up: u32) -> FixedTimestep {
+        FixedTimestep {
+            tick_length,
+            next_tick: start + tick_length,
+            max_catch_up: max_catch_up.max(1),
+        }
+    }
+
+    pub fn next_tick(&self) -> Instant {
+        self.next_tick
+    }
+
+    // how many steps to run now; when more than max_catch_up are overdue the rest are dropped
+    // rather than fast forwarding the battle, and the schedule keeps its original phase
+    pub fn due(&mut self, now: Instant) -> u32 {
+        if now < self.next_tick {
+            return 0;
+        }
+
+        let overdue =
+            ((now - self.next_tick).as_nanos() / self.tick_length.as_nanos().max(1)) as u32 + 1;
+        self.next_tick += self.tick_length * overdue;
+        overdue.min(self.max_catch_up)
+    }
+}
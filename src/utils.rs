@@ -1,78 +1,97 @@
 use std::time::SystemTime;
-use crossterm::style::Color;
 
-use crate::lib::*;
+use crate::catalogue::*;
+use crate::map::*;
+use crate::*;
 
-pub(crate) fn now() -> u64 {
+pub fn now() -> u64 {
     SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_secs()
 }
 
-
-pub fn generate_barbarian(y: i16, c: Color) -> Character {
-    let x = if c == Color::Green { 1 } else { 68 };
-
+// unique_id is left at 0, World::spawn hands out the real one
+pub fn generate_unit(unit: &UnitDef, x: i16, y: i16, team: Team) -> Character {
     Character {
-        unique_id: fastrand::u16(0..65_000),
-        x: x,
-        y: y,
-        denotation: 'B',
-        color: c,
-        hp: 12,
-        attack_skill: 3,
-        defence_class: 9,
-        attack_range: 1,
-        damage_range: (1..7),
-        attack_rate: 5,
-        attack_cooldown: 5,
-        movement_rate: 7,
-        movement_cooldown: 7,
+        unique_id: 0,
+        x,
+        y,
+        denotation: unit.denotation,
+        team,
+        hp: unit.hp,
+        attack_skill: unit.attack_skill,
+        defence_class: unit.defence_class,
+        attack_range: unit.attack_range,
+        damage_range: unit.damage_range.clone(),
+        attack_rate: unit.attack_rate,
+        attack_cooldown: unit.attack_rate,
+        movement_rate: unit.movement_rate,
+        movement_cooldown: unit.movement_rate,
         is_attacking: false,
     }
 }
 
-pub fn generate_archer(y: i16, c: Color) -> Character {
-    let x = if c == Color::Green { 1 } else { 68 };
+// the stock units from the built in catalogue, whatever file the server has loaded
+pub fn generate_barbarian(y: i16, c: Team) -> Character {
+    generate_built_in("barbarian", y, c)
+}
 
-    Character {
-        unique_id: fastrand::u16(0..65_000),
-        x: x,
-        y: y,
-        denotation: 'A',
-        color: c,
-        hp: 6,
-        attack_skill: 2,
-        defence_class: 7,
-        attack_range: 5,
-        damage_range: (1..4),
-        attack_rate: 10,
-        attack_cooldown: 10,
-        movement_rate: 13,
-        movement_cooldown: 13,
-        is_attacking: false,
-    }
+pub fn generate_archer(y: i16, c: Team) -> Character {
+    generate_built_in("archer", y, c)
 }
 
-pub fn generate_giant(y: i16, c: Color) -> Character {
-    let x = if c == Color::Green { 1 } else { 68 };
+pub fn generate_giant(y: i16, c: Team) -> Character {
+    generate_built_in("giant", y, c)
+}
 
-    Character {
-        unique_id: fastrand::u16(0..65_000),
-        x: x,
-        y: y,
-        denotation: 'G',
-        color: c,
-        hp: 30,
-        attack_skill: 4,
-        defence_class: 12,
-        attack_range: 1,
-        damage_range: (6..12),
-        attack_rate: 15,
-        attack_cooldown: 15,
-        movement_rate: 30,
-        movement_cooldown: 30,
-        is_attacking: false,
+// on the column the built in map spawns that side on
+fn generate_built_in(name: &str, y: i16, c: Team) -> Character {
+    let unit = Catalogue::built_in().unit(name).unwrap();
+    let x = Map::built_in().spawn_points(c).first().map_or(0, |p| p.0);
+    generate_unit(unit, x, y, c)
+}
+
+// every point on the straight line between the two, both ends included
+pub fn calc_line(x1: i32, y1: i32, x2: i32, y2: i32) -> Vec<(i32, i32)> {
+    let mut coordinates: Vec<(i32, i32)> = vec![];
+    let dx = i32::abs(x2 - x1);
+    let dy = i32::abs(y2 - y1);
+    let sx = {
+        if x1 < x2 {
+            1
+        } else {
+            -1
+        }
+    };
+    let sy = {
+        if y1 < y2 {
+            1
+        } else {
+            -1
+        }
+    };
+
+    let mut error = (if dx > dy { dx } else { -dy }) / 2;
+    let mut current_x = x1;
+    let mut current_y = y1;
+    loop {
+        coordinates.push((current_x, current_y));
+
+        if current_x == x2 && current_y == y2 {
+            break;
+        }
+
+        let error2 = error;
+
+        if error2 > -dx {
+            error -= dy;
+            current_x += sx;
+        }
+        if error2 < dy {
+            error += dx;
+            current_y += sy;
+        }
     }
+    coordinates
 }
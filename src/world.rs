@@ -0,0 +1,383 @@
+use std::cmp::Ordering;
+
+use fastrand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::events::*;
+use crate::pathfinding::*;
+use crate::spatial::*;
+use crate::terrain::*;
+use crate::*;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WinCondition {
+    // the battle ends once only one side has anyone left standing
+    #[default]
+    LastTeamStanding,
+    // as above, but after this many ticks the side with more units standing wins
+    TimeLimit(u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Won(Team),
+    // both sides fell on the same tick, or time ran out with the numbers level
+    Draw,
+}
+
+// the battle rules on their own: no sockets, no files, and the dice can be swapped out
+pub struct World {
+    tick: u64,
+    next_id: u16,
+    pieces: Vec<Character>,
+    terrain: Terrain,
+    // where the living pieces stand, kept up to date as they move and fall
+    index: SpatialIndex,
+    // the sides that have put at least one unit on the field
+    fielded: Vec<Team>,
+    seed: Option<u64>,
+    rng: Rng,
+    // what happened since the events were last taken
+    events: Vec<CombatEvent>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::with_seed(fastrand::u64(..))
+    }
+}
+
+impl World {
+    pub fn new() -> World {
+        World::default()
+    }
+
+    // every roll and shuffle comes from this seed, so the same seed and spawns replay the same battle
+    pub fn with_seed(seed: u64) -> World {
+        World {
+            seed: Some(seed),
+            ..World::with_rng(Rng::with_seed(seed))
+        }
+    }
+
+    pub fn with_rng(rng: Rng) -> World {
+        World {
+            tick: 0,
+            next_id: 0,
+            pieces: Vec::new(),
+            terrain: Terrain::default(),
+            index: SpatialIndex::new(FIELD_WIDTH, FIELD_HEIGHT),
+            fielded: Vec::new(),
+            seed: None,
+            rng,
+            events: Vec::new(),
+        }
+    }
+
+    // None when the world was handed an rng rather than a seed
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    // the world hands out unique_ids itself, whatever the piece arrived with is replaced;
+    // once the ids wrap around any still on the field are skipped, and with every one of them
+    // taken the piece is turned away
+    pub fn spawn(&mut self, mut piece: Character) -> Option<u16> {
+        piece.unique_id = self.next_id;
+        while self.piece(piece.unique_id).is_some() {
+            piece.unique_id = piece.unique_id.wrapping_add(1);
+            if piece.unique_id == self.next_id {
+                return None;
+            }
+        }
+        self.next_id = piece.unique_id.wrapping_add(1);
+        if !self.fielded.contains(&piece.team) {
+            self.fielded.push(piece.team);
+        }
+        if piece.hp > 0 {
+            self.index.insert(self.pieces.len(), &piece);
+        }
+        self.events.push(CombatEvent::Spawned {
+            unit: Fighter::of(&piece),
+            at: (piece.x, piece.y),
+            hp: piece.hp,
+        });
+        self.pieces.push(piece);
+        self.pieces.last().map(|p| p.unique_id)
+    }
+
+    pub fn step(&mut self) {
+        // the fallen get one tick on the field so everyone sees them go down, then they are cleared
+        if self.pieces.iter().any(|p| p.hp <= 0) {
+            self.pieces.retain(|p| p.hp > 0);
+            self.index =
+                SpatialIndex::build(&self.pieces, self.terrain.width(), self.terrain.height());
+        }
+
+        if self.pieces.len() > 1 {
+            update_movement(
+                &mut self.pieces,
+                &mut self.index,
+                &self.terrain,
+                &self.rng,
+                &mut self.events,
+            );
+            update_attacks(
+                &mut self.pieces,
+                &mut self.index,
+                &self.terrain,
+                &self.rng,
+                &mut self.events,
+            );
+        }
+        self.tick += 1;
+    }
+
+    // the ground is laid before anyone is spawned, and stays put for the whole battle
+    pub fn set_terrain(&mut self, terrain: Terrain) {
+        self.index = SpatialIndex::build(&self.pieces, terrain.width(), terrain.height());
+        self.terrain = terrain;
+    }
+
+    pub fn terrain(&self) -> &Terrain {
+        &self.terrain
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn pieces(&self) -> &[Character] {
+        &self.pieces
+    }
+
+    pub fn piece(&self, unique_id: u16) -> Option<&Character> {
+        self.pieces.iter().find(|p| p.unique_id == unique_id)
+    }
+
+    pub fn living(&self) -> impl Iterator<Item = &Character> {
+        self.pieces.iter().filter(|p| p.hp > 0)
+    }
+
+    pub fn living_count(&self, team: Team) -> usize {
+        self.living().filter(|p| p.team == team).count()
+    }
+
+    // None while the battle is still on; a side that has not fielded anyone yet cannot lose
+    pub fn outcome(&self, condition: WinCondition) -> Option<Outcome> {
+        if let WinCondition::TimeLimit(limit) = condition {
+            if self.tick >= limit {
+                let greens = self.living_count(Team::Green);
+                let reds = self.living_count(Team::Red);
+                return Some(match greens.cmp(&reds) {
+                    Ordering::Greater => Outcome::Won(Team::Green),
+                    Ordering::Less => Outcome::Won(Team::Red),
+                    Ordering::Equal => Outcome::Draw,
+                });
+            }
+        }
+
+        if self.fielded.len() < 2 {
+            return None;
+        }
+        let standing: Vec<Team> = self
+            .fielded
+            .iter()
+            .copied()
+            .filter(|team| self.living_count(*team) > 0)
+            .collect();
+        match standing[..] {
+            [] => Some(Outcome::Draw),
+            [team] => Some(Outcome::Won(team)),
+            _ => None,
+        }
+    }
+
+    // everything that happened since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<CombatEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+fn update_movement(
+    pieces: &mut [Character],
+    index: &mut SpatialIndex,
+    terrain: &Terrain,
+    rng: &Rng,
+    events: &mut Vec<CombatEvent>,
+) {
+    let mut ids: Vec<usize> = (0..pieces.len()).collect();
+    rng.shuffle(&mut ids);
+
+    // worked out once per team per tick, the first time one of its units is ready to move
+    let mut fields: Vec<(Team, FlowField)> = Vec::new();
+
+    for i in ids {
+        if pieces[i].hp < 1 {
+            continue;
+        }
+
+        // need to play test if letting movement cooldown continue during attacking or not has a positive/negative effect on play
+        if pieces[i].is_attacking {
+            continue;
+        }
+
+        pieces[i].movement_cooldown -= 1;
+
+        if pieces[i].movement_cooldown <= 0 {
+            if let Some(closest_enemy) = index.nearest_enemy(pieces, i) {
+                let mut movex = 0;
+                let mut movey = 0;
+
+                // enemy located
+                if pieces[i].x < pieces[closest_enemy].x {
+                    movex = 1_i16;
+                }
+                if pieces[i].x > pieces[closest_enemy].x {
+                    movex = -1_i16;
+                }
+                if pieces[i].y < pieces[closest_enemy].y {
+                    movey = 1_i16;
+                }
+                if pieces[i].y > pieces[closest_enemy].y {
+                    movey = -1_i16;
+                }
+
+                let team = pieces[i].team;
+                let field = match fields.iter().position(|(t, _)| *t == team) {
+                    Some(f) => &fields[f].1,
+                    None => {
+                        fields.push((team, FlowField::towards_enemies_of(team, pieces, terrain)));
+                        &fields.last().unwrap().1
+                    }
+                };
+
+                if let Some((stepx, stepy)) = next_step(pieces, index, i, field, (movex, movey)) {
+                    let from = (pieces[i].x, pieces[i].y);
+                    index.relocate(i, from, (from.0 + stepx, from.1 + stepy));
+                    pieces[i].x += stepx;
+                    pieces[i].y += stepy;
+                    events.push(CombatEvent::Moved {
+                        unit: Fighter::of(&pieces[i]),
+                        from,
+                        to: (pieces[i].x, pieces[i].y),
+                    });
+                    pieces[i].movement_cooldown = terrain
+                        .tile(pieces[i].x, pieces[i].y)
+                        .movement_rate(pieces[i].movement_rate);
+                }
+            }
+        }
+    }
+}
+
+// the free neighbouring cell that gets closest to an enemy, heading straight for the nearest one when that is as good
+fn next_step(
+    pieces: &[Character],
+    index: &SpatialIndex,
+    i: usize,
+    field: &FlowField,
+    towards: (i16, i16),
+) -> Option<(i16, i16)> {
+    let (x, y) = (pieces[i].x, pieces[i].y);
+
+    // the unit's own cell is walled off by itself, so judge it by the best of its neighbours
+    let here = NEIGHBOURS
+        .iter()
+        .filter_map(|(dx, dy)| field.distance(x + dx, y + dy))
+        .min()?
+        + 1;
+
+    let mut best: Option<(u16, (i16, i16))> = None;
+    for (dx, dy) in NEIGHBOURS {
+        let Some(distance) = field.distance(x + dx, y + dy) else {
+            continue;
+        };
+        if distance >= here || index.occupied(x + dx, y + dy) {
+            continue;
+        }
+        let better = match best {
+            None => true,
+            Some((d, _)) => distance < d || (distance == d && (dx, dy) == towards),
+        };
+        if better {
+            best = Some((distance, (dx, dy)));
+        }
+    }
+    best.map(|(_, step)| step)
+}
+
+fn update_attacks(
+    pieces: &mut [Character],
+    index: &mut SpatialIndex,
+    terrain: &Terrain,
+    rng: &Rng,
+    events: &mut Vec<CombatEvent>,
+) {
+    let mut ids: Vec<usize> = (0..pieces.len()).collect();
+    rng.shuffle(&mut ids);
+
+    for i in ids {
+        if pieces[i].hp < 1 {
+            continue;
+        }
+
+        if pieces[i].attack_cooldown > 0 {
+            pieces[i].attack_cooldown -= 1;
+        } else {
+            pieces[i].is_attacking = false;
+
+            // everyone in range, whoever falls along the way is skipped by the hp check
+            for j in index.within(pieces, pieces[i].x, pieces[i].y, pieces[i].attack_range) {
+                // check that the item is not an enemy, is alive and is not behind a wall
+                let (from, to) = ((pieces[i].x, pieces[i].y), (pieces[j].x, pieces[j].y));
+                if pieces[i].team != pieces[j].team
+                    && pieces[j].hp > 0
+                    && terrain.line_of_sight(from, to)
+                {
+                    let (attacker, target) = (Fighter::of(&pieces[i]), Fighter::of(&pieces[j]));
+                    events.push(CombatEvent::AttackStarted { attacker, target });
+
+                    // pause moving while attacking - glass cannons don't want to be walking to their death
+                    pieces[i].is_attacking = true;
+
+                    // attack rolls
+                    // 2d6 + attack_skill > enemy defence_class, plus whatever cover the ground gives
+                    let defence =
+                        pieces[j].defence_class + terrain.tile(to.0, to.1).defence_bonus();
+
+                    let roll = rng.i16(1..7) + rng.i16(1..7) + pieces[i].attack_skill;
+                    events.push(CombatEvent::Rolled {
+                        attacker,
+                        target,
+                        roll,
+                        defence,
+                    });
+
+                    if roll >= defence {
+                        // passed check, do damage
+                        let damage =
+                            rng.i16(pieces[i].damage_range.start..pieces[i].damage_range.end);
+                        pieces[j].hp -= damage;
+                        events.push(CombatEvent::Hit {
+                            attacker,
+                            target,
+                            damage,
+                            hp_left: pieces[j].hp,
+                        });
+
+                        if pieces[j].hp <= 0 {
+                            index.remove(j, &pieces[j]);
+                            pieces[i].is_attacking = false;
+                            events.push(CombatEvent::Killed { attacker, target });
+                        }
+                    } else {
+                        events.push(CombatEvent::Miss { attacker, target });
+                    }
+
+                    pieces[i].attack_cooldown = pieces[i].attack_rate;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use async_std::task::spawn;
+
+use fracas::{ai::*, catalogue::*, map::*, net::*, utils::*, world::*, *};
+
+mod common;
+use common::*;
+
+fn opponent(difficulty: Difficulty, gold: u32) -> Opponent {
+    let mut opponent = Opponent::new(
+        difficulty,
+        Team::Red,
+        Catalogue::built_in().clone(),
+        Map::built_in().clone(),
+        7,
+    );
+    opponent.set_gold(gold);
+    opponent
+}
+
+fn spawns(messages: &[ClientMessage]) -> Vec<(String, u8)> {
+    messages
+        .iter()
+        .map(|m| match m {
+            ClientMessage::SpawnUnit { unit, row } => (unit.clone(), *row),
+            other => panic!("expected a spawn, got {:?}", other),
+        })
+        .collect()
+}
+
+fn green(unit: &str, y: i16) -> Character {
+    let unit = Catalogue::built_in().unit(unit).unwrap();
+    generate_unit(unit, 1, y, Team::Green)
+}
+
+#[test]
+fn the_odds_follow_the_dice() {
+    assert_eq!(hit_chance(0, 2), 1.0);
+    assert_eq!(hit_chance(3, 9), 26.0 / 36.0);
+    assert_eq!(hit_chance(0, 13), 0.0);
+
+    let catalogue = Catalogue::built_in();
+    let unit = |name| catalogue.unit(name).unwrap();
+    assert_eq!(edge(unit("giant"), unit("giant")), 1.0);
+    assert!(edge(unit("giant"), unit("barbarian")) > 1.0);
+    assert!(edge(unit("barbarian"), unit("archer")) > 1.0);
+    assert!(worth(unit("giant"), catalogue) > worth(unit("archer"), catalogue));
+}
+
+#[test]
+fn the_random_opponent_keeps_pace_with_the_player() {
+    let mut random = opponent(Difficulty::Random, 1_000);
+    let mut pieces = Vec::new();
+
+    // it gets one ahead on numbers and then waits for the player
+    for _ in 0..5 {
+        for (unit, row) in spawns(&random.think(MatchPhase::Running, &pieces)) {
+            assert!((1..=9).contains(&row));
+            let unit = Catalogue::built_in().unit(&unit).unwrap();
+            let (x, y) = Map::built_in().spawn_point(Team::Red, row).unwrap();
+            pieces.push(generate_unit(unit, x, y, Team::Red));
+        }
+    }
+    assert_eq!(pieces.len(), 2);
+
+    pieces.push(green("barbarian", 4));
+    assert_eq!(random.think(MatchPhase::Running, &pieces).len(), 1);
+
+    // only ever what it can pay for, archers are all there is for 80 gold
+    pieces.extend((0..3).map(|y| green("barbarian", y * 2)));
+    random.set_gold(0);
+    assert!(random.think(MatchPhase::Running, &pieces).is_empty());
+    random.set_gold(80);
+    let bought = spawns(&random.think(MatchPhase::Running, &pieces));
+    assert_eq!(bought.len(), 1);
+    assert_eq!(bought[0].0, "archer");
+    assert_eq!(random.gold(), 5);
+
+    // and does nothing at all once the battle is over
+    pieces.clear();
+    let over = MatchPhase::Finished(Outcome::Draw);
+    assert!(random.think(over, &pieces).is_empty());
+}
+
+#[test]
+fn the_counter_picker_answers_what_the_player_fields() {
+    let mut counter = opponent(Difficulty::Counter, 0);
+
+    for (player, answer) in [
+        ("giant", "archer"),
+        ("barbarian", "giant"),
+        ("archer", "barbarian"),
+    ] {
+        let pieces = vec![green(player, 14), green(player, 16)];
+        counter.set_gold(1_000);
+        assert_eq!(
+            spawns(&counter.think(MatchPhase::Running, &pieces)),
+            [(answer.to_string(), 7)],
+            "against {player}s"
+        );
+    }
+
+    // short of a giant it makes do with the best it can afford
+    let pieces = vec![green("barbarian", 14), green("barbarian", 16)];
+    counter.set_gold(100);
+    assert_eq!(
+        spawns(&counter.think(MatchPhase::Running, &pieces)),
+        [("barbarian".to_string(), 7)]
+    );
+    assert_eq!(counter.gold(), 0);
+    assert!(counter.think(MatchPhase::Running, &pieces).is_empty());
+}
+
+#[test]
+fn the_economy_opponent_saves_up_and_spends_when_behind() {
+    let mut economy = opponent(Difficulty::Economy, 0);
+    let catalogue = Catalogue::built_in();
+    let giant = catalogue.unit("giant").unwrap();
+
+    // barbarians are best met with giants for the gold, so it saves until it can afford one
+    // rather than settle for what it could buy already
+    let mut pieces = vec![green("barbarian", 2)];
+    for gold in [0, 100, giant.cost - 1] {
+        economy.set_gold(gold);
+        assert!(economy.think(MatchPhase::Lobby, &pieces).is_empty());
+        assert_eq!(economy.gold(), gold);
+    }
+    economy.set_gold(giant.cost);
+    let bought = spawns(&economy.think(MatchPhase::Lobby, &pieces));
+    assert_eq!(bought.len(), 1);
+    assert_eq!(bought[0].0, "giant");
+    assert_eq!(economy.gold(), 0);
+
+    // well ahead, it banks its gold
+    pieces.push(generate_unit(giant, 68, 2, Team::Red));
+    economy.set_gold(1_000);
+    for _ in 0..10 {
+        assert!(economy.think(MatchPhase::Running, &pieces).is_empty());
+    }
+    assert_eq!(economy.gold(), 1_000);
+
+    // until the player outnumbers it, then it spends what it has in one go
+    pieces.extend((0..8).map(|y| green("barbarian", y * 2)));
+    let bought = spawns(&economy.think(MatchPhase::Running, &pieces));
+    assert!(bought.len() > 1);
+    assert!(bought.iter().all(|(unit, _)| unit == "giant"));
+    assert_eq!(economy.gold(), 1_000 - bought.len() as u32 * giant.cost);
+}
+
+#[async_std::test]
+async fn the_computer_joins_as_red_and_plays_like_anyone_else() {
+    let address = start_server().await;
+    let (mut host, code) = host_game(address).await;
+    spawn(play(address.to_string(), code, Difficulty::Counter));
+
+    // it joins on the other side and opens without waiting for the host
+    let pieces = snapshot_where(&mut host, |p| !p.is_empty()).await;
+    assert!(pieces.iter().all(|p| p.team == Team::Red && p.x == 68));
+
+    // having readied up straight away, so the countdown starts as soon as the host does
+    send_message(&mut host, &ClientMessage::Ready)
+        .await
+        .unwrap();
+    loop {
+        if let ServerMessage::Phase(MatchPhase::Countdown { .. }) = next_reply(&mut host).await {
+            break;
+        }
+    }
+}
+
+#[async_std::test]
+async fn the_computer_says_why_it_stopped() {
+    let address = start_server().await;
+    let why = play(address.to_string(), "nope".to_string(), Difficulty::Random).await;
+    assert_eq!(why, "The computer could not find session nope");
+
+    // nothing listens here once the listener is gone
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let gone = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let why = play(gone.clone(), "nope".to_string(), Difficulty::Random).await;
+    assert!(why.starts_with(&format!("The computer could not reach {gone}")));
+}
@@ -0,0 +1,133 @@
+use fracas::{balance::*, catalogue::*, map::*, world::*};
+
+fn simulation(matchup: &str, battles: u64) -> Simulation {
+    let catalogue = Catalogue::built_in().clone();
+    let (green, red) = Army::matchup(matchup, &catalogue).unwrap();
+    Simulation {
+        green,
+        red,
+        catalogue,
+        map: Map::built_in().clone(),
+        win_condition: WinCondition::LastTeamStanding,
+        battles,
+        seed: 11,
+    }
+}
+
+#[test]
+fn armies_are_read_the_way_people_write_them() {
+    let catalogue = Catalogue::built_in();
+
+    let (green, red) = Army::matchup("5 barbarians vs 2 Giants + 3 archers", catalogue).unwrap();
+    assert_eq!(green.units, [(5, "barbarian".to_string())]);
+    assert_eq!(
+        red.units,
+        [(2, "giant".to_string()), (3, "archer".to_string())]
+    );
+    assert_eq!(red.to_string(), "2 giant + 3 archer");
+
+    // a missing count is one, and the same unit twice is added up
+    let army = Army::parse("giant, 2 giants", catalogue).unwrap();
+    assert_eq!(army.units, [(3, "giant".to_string())]);
+
+    assert_eq!(
+        Army::matchup("5 barbarians", catalogue),
+        Err(ArmyError::NotAMatchup)
+    );
+    assert_eq!(Army::parse(" + ", catalogue), Err(ArmyError::Empty));
+    assert_eq!(Army::parse("0 giants", catalogue), Err(ArmyError::Empty));
+    assert_eq!(
+        Army::parse("2 dragons", catalogue),
+        Err(ArmyError::UnknownUnit("dragons".to_string()))
+    );
+
+    // more than the field could ever hold is turned away rather than fought
+    assert_eq!(
+        Army::parse("70000 barbarians", catalogue),
+        Err(ArmyError::TooBig(70_000))
+    );
+    assert_eq!(
+        Army::parse("6000 giants + 6000 archers", catalogue),
+        Err(ArmyError::TooBig(12_000))
+    );
+    assert!(Army::parse(&format!("{MAX_ARMY} archers"), catalogue).is_ok());
+}
+
+#[test]
+fn a_battle_is_the_same_every_time_for_its_seed() {
+    let simulation = simulation("2 barbarians vs 3 archers", 1);
+
+    let first = simulation.fight(5);
+    assert_eq!(first, simulation.fight(5));
+    assert_eq!(first.seed, 5);
+    assert_eq!(first.damage.len(), 2);
+    assert!(first.winner.is_some());
+    assert!(first.ticks > 0);
+}
+
+#[test]
+fn the_report_adds_up() {
+    let report = simulation("barbarian vs archer", 12).run();
+
+    assert_eq!(report.battles, 12);
+    assert_eq!(report.results.len(), 12);
+    assert_eq!(
+        report.green_wins + report.red_wins + report.draws + report.stalemates,
+        12
+    );
+
+    // every battle fought with its own seed, in order
+    let seeds: Vec<u64> = report.results.iter().map(|r| r.seed).collect();
+    assert_eq!(seeds, (11..23).collect::<Vec<u64>>());
+
+    let units: Vec<(&str, &str, usize)> = report
+        .units
+        .iter()
+        .map(|u| (u.team.as_str(), u.unit.as_str(), u.count))
+        .collect();
+    assert_eq!(units, [("green", "barbarian", 1), ("red", "archer", 1)]);
+    assert!(report.average_ticks > 0.0);
+}
+
+#[test]
+fn a_lopsided_matchup_goes_the_way_it_should() {
+    let report = simulation("3 giants vs 1 archer", 10).run();
+
+    assert_eq!(report.green_wins, 10);
+    assert_eq!(report.win_rate(report.green_wins), 1.0);
+    assert_eq!(report.average_red_hp, 0.0);
+    assert!(report.units[0].damage_dealt >= 6.0);
+}
+
+#[test]
+fn a_time_limit_ends_battles_early() {
+    let mut simulation = simulation("3 giants vs 3 giants", 4);
+    simulation.win_condition = WinCondition::TimeLimit(10);
+    let report = simulation.run();
+
+    assert!(report.results.iter().all(|r| r.ticks <= 10));
+    assert_eq!(report.green_wins + report.red_wins + report.draws, 4);
+}
+
+#[test]
+fn reports_come_out_as_csv_and_json() {
+    let report = simulation("barbarian vs 2 archers", 3).run();
+
+    let csv = report.to_csv();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "seed,winner,ticks,green_hp,red_hp,green_barbarian_damage,red_archer_damage"
+    );
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with("11,"));
+    assert!(lines[1..].iter().all(|l| l.split(',').count() == 7));
+
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json["battles"], 3);
+    assert_eq!(json["map"], "classic");
+    assert_eq!(json["units"][1]["unit"], "archer");
+    assert_eq!(json["units"][1]["count"], 2);
+    // the per battle results are for the csv, not the summary
+    assert!(json.get("results").is_none());
+}
@@ -0,0 +1,146 @@
+use fracas::{catalogue::*, server::*, *};
+
+mod common;
+use common::*;
+
+const WOLF: &str = r#"(
+    units: [
+        (
+            name: "wolf",
+            key: 'w',
+            denotation: 'W',
+            hp: 8,
+            attack_skill: 3,
+            damage_range: (start: 1, end: 5),
+            defence_class: 8,
+            attack_range: 1,
+            attack_rate: 4,
+            movement_rate: 3,
+        ),
+    ],
+)"#;
+
+// the text of the first unit in a catalogue
+fn unit_entry(catalogue: &str) -> &str {
+    let start = catalogue.find("        (").unwrap();
+    let end = catalogue.find("        ),").unwrap() + "        )".len();
+    &catalogue[start..end]
+}
+
+#[test]
+fn the_built_in_catalogue_has_the_stock_units() {
+    let catalogue = Catalogue::built_in();
+    let keys: Vec<char> = catalogue.units.iter().map(|u| u.key).collect();
+    assert_eq!(keys, vec!['b', 'a', 'g']);
+
+    let giant = catalogue.by_key('g').unwrap();
+    assert_eq!(giant.name, "giant");
+    assert_eq!(giant.hp, 30);
+    assert_eq!(giant.damage_range, 6..12);
+    assert!(catalogue.unit("dragon").is_none());
+}
+
+#[test]
+fn spawned_units_take_their_stats_from_the_catalogue() {
+    let catalogue = Catalogue::parse(WOLF).unwrap();
+    let wolf = utils::generate_unit(catalogue.unit("wolf").unwrap(), 68, 6, Team::Red);
+
+    assert_eq!((wolf.x, wolf.y), (68, 6));
+    assert_eq!(wolf.denotation, 'W');
+    assert_eq!(wolf.hp, 8);
+    assert_eq!(wolf.attack_cooldown, 4);
+    assert_eq!(wolf.movement_cooldown, 3);
+}
+
+#[test]
+fn broken_catalogues_are_refused() {
+    assert!(matches!(
+        Catalogue::parse("(units: [(name: \"wolf\")])"),
+        Err(CatalogueError::Parse(_))
+    ));
+    assert!(matches!(
+        Catalogue::load(std::path::Path::new("no/such/units.ron")),
+        Err(CatalogueError::Io(_))
+    ));
+
+    for (from, to) in [
+        ("name: \"wolf\"", "name: \"\""),
+        ("key: 'w'", "key: 't'"),
+        ("key: 'w'", "key: 'W'"),
+        ("denotation: 'W'", "denotation: ' '"),
+        ("hp: 8", "hp: 0"),
+        ("(start: 1, end: 5)", "(start: 5, end: 5)"),
+        ("(start: 1, end: 5)", "(start: -2, end: 5)"),
+        ("movement_rate: 3", "movement_rate: -1"),
+    ] {
+        let broken = WOLF.replace(from, to);
+        assert!(
+            matches!(Catalogue::parse(&broken), Err(CatalogueError::Invalid(_))),
+            "{to} was accepted"
+        );
+    }
+
+    let twice = WOLF.replace("    ],", &format!("{},\n    ],", unit_entry(WOLF)));
+    assert!(matches!(
+        Catalogue::parse(&twice),
+        Err(CatalogueError::Invalid(_))
+    ));
+    // a fox that looks just like a wolf on the field
+    let fox = unit_entry(WOLF)
+        .replace("\"wolf\"", "\"fox\"")
+        .replace("'w'", "'f'");
+    let lookalike = WOLF.replace("    ],", &format!("{fox},\n    ],"));
+    assert!(matches!(
+        Catalogue::parse(&lookalike),
+        Err(CatalogueError::Invalid(_))
+    ));
+    assert!(matches!(
+        Catalogue::parse("(units: [])"),
+        Err(CatalogueError::Invalid(_))
+    ));
+}
+
+#[async_std::test]
+async fn clients_get_the_servers_catalogue_on_join() {
+    let config = ServerConfig {
+        catalogue: Catalogue::parse(WOLF).unwrap(),
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+
+    let mut host = async_std::net::TcpStream::connect(address).await.unwrap();
+    let code = match request(&mut host, &ClientMessage::NewGame { map: None }).await {
+        ServerMessage::GameCreated {
+            code, catalogue, ..
+        } => {
+            assert_eq!(catalogue, Catalogue::parse(WOLF).unwrap());
+            code
+        }
+        reply => panic!("expected a new game, got {:?}", reply),
+    };
+
+    // the stock units mean nothing to this server
+    let spawn = |unit: &str| ClientMessage::SpawnUnit {
+        unit: unit.to_string(),
+        row: 4,
+    };
+    assert!(matches!(
+        request(&mut host, &spawn("barbarian")).await,
+        ServerMessage::Error(ServerError::UnknownUnit(_))
+    ));
+    assert!(matches!(
+        request(&mut host, &spawn("wolf")).await,
+        ServerMessage::SpawnAccepted
+    ));
+
+    let mut joiner = async_std::net::TcpStream::connect(address).await.unwrap();
+    let join = ClientMessage::JoinGame { code };
+    match request(&mut joiner, &join).await {
+        ServerMessage::GameJoined { catalogue, .. } => {
+            assert_eq!(catalogue.units[0].name, "wolf")
+        }
+        reply => panic!("expected to join, got {:?}", reply),
+    }
+    let pieces = snapshot_where(&mut joiner, |p| !p.is_empty()).await;
+    assert_eq!(pieces[0].denotation, 'W');
+}
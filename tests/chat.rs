@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use async_std::{future::timeout, net::TcpStream, task::sleep};
+
+use fracas::{net::*, server::*, *};
+
+mod common;
+use common::*;
+
+async fn say(stream: &mut TcpStream, text: &str) {
+    let chat = ClientMessage::Chat {
+        text: text.to_string(),
+    };
+    send_message(stream, &chat).await.unwrap();
+}
+
+async fn set_name(stream: &mut TcpStream, name: &str) {
+    let set_name = ClientMessage::SetName {
+        name: name.to_string(),
+    };
+    send_message(stream, &set_name).await.unwrap();
+}
+
+async fn next_chat(stream: &mut TcpStream) -> (String, Team, String) {
+    match next_reply(stream).await {
+        ServerMessage::Chat {
+            name, team, text, ..
+        } => (name, team, text),
+        reply => panic!("expected a chat line, got {:?}", reply),
+    }
+}
+
+#[async_std::test]
+async fn chat_reaches_everyone_in_the_session() {
+    let address = start_server().await;
+    let (mut host, code) = host_game(address).await;
+    let mut joiner = join_game(address, &code).await;
+
+    set_name(&mut host, "alice").await;
+    say(&mut host, "hello there").await;
+
+    for stream in [&mut host, &mut joiner] {
+        let line = next_chat(stream).await;
+        assert_eq!(
+            line,
+            ("alice".to_string(), Team::Green, "hello there".to_string())
+        );
+    }
+
+    // without a name the server falls back to the player number
+    say(&mut joiner, "hi").await;
+    let (name, team, _) = next_chat(&mut host).await;
+    assert_eq!(name, "Player 1");
+    assert_eq!(team, Team::Red);
+}
+
+#[async_std::test]
+async fn chat_reaches_a_player_who_has_fallen_behind() {
+    let config = ServerConfig {
+        tick_rate: 200,
+        starting_gold: 300 * 100,
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+    let (mut host, code) = host_game(address).await;
+    let mut joiner = join_game(address, &code).await;
+    crowd(&mut host, 300).await;
+
+    // the joiner reads nothing while snapshots of the crowded field pile up for it
+    sleep(Duration::from_secs(2)).await;
+    say(&mut host, "still there?").await;
+
+    let line = timeout(Duration::from_secs(10), next_chat(&mut joiner))
+        .await
+        .expect("the chat line was dropped");
+    assert_eq!(line.2, "still there?");
+}
+
+#[async_std::test]
+async fn chat_stays_inside_its_session() {
+    let address = start_server().await;
+    let (mut first, _) = host_game(address).await;
+    let (mut second, _) = host_game(address).await;
+
+    say(&mut first, "only for the first game").await;
+    assert_eq!(next_chat(&mut first).await.2, "only for the first game");
+
+    // the second session's next line is its own, nothing leaked across
+    say(&mut second, "second game here").await;
+    assert_eq!(next_chat(&mut second).await.2, "second game here");
+}
+
+#[async_std::test]
+async fn chat_lines_are_cleaned_up() {
+    let address = start_server().await;
+    let (mut host, _) = host_game(address).await;
+
+    set_name(&mut host, &"n".repeat(100)).await;
+    say(&mut host, "   ").await;
+    say(&mut host, "\x1b[2Jgotcha\n").await;
+    say(&mut host, &"x".repeat(1000)).await;
+
+    // the blank line is dropped, so the escape sequence is the first thing back
+    let (name, _, text) = next_chat(&mut host).await;
+    assert_eq!(name.len(), MAX_NAME_LEN);
+    assert_eq!(text, "[2Jgotcha");
+
+    let (_, _, text) = next_chat(&mut host).await;
+    assert_eq!(text.len(), MAX_CHAT_LEN);
+}
+
+#[async_std::test]
+async fn chat_needs_a_session() {
+    let address = start_server().await;
+    let mut stream = TcpStream::connect(address).await.unwrap();
+
+    for message in [
+        ClientMessage::Chat {
+            text: "anyone?".to_string(),
+        },
+        ClientMessage::SetName {
+            name: "nobody".to_string(),
+        },
+    ] {
+        assert!(matches!(
+            request(&mut stream, &message).await,
+            ServerMessage::Error(ServerError::NotInSession)
+        ));
+    }
+}
@@ -0,0 +1,90 @@
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+
+use async_std::{
+    net::{TcpListener, TcpStream},
+    task::spawn,
+};
+
+use fracas::{net::*, server::*, *};
+
+pub async fn start_server() -> SocketAddr {
+    start_server_with(ServerConfig::default()).await
+}
+
+pub async fn start_server_with(config: ServerConfig) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let config = ServerConfig {
+        log_path: std::env::temp_dir().join("fracas-test.log"),
+        ..config
+    };
+    spawn(server(listener, config));
+    address
+}
+
+// the server pushes a snapshot every tick, and events and gold as they happen, so skip those until the direct reply turns up
+pub async fn next_reply(stream: &mut TcpStream) -> ServerMessage {
+    loop {
+        match recv_message(stream).await.unwrap() {
+            ServerMessage::Snapshot { .. }
+            | ServerMessage::Delta { .. }
+            | ServerMessage::Events { .. }
+            | ServerMessage::Gold { .. } => continue,
+            reply => return reply,
+        }
+    }
+}
+
+pub async fn request(stream: &mut TcpStream, message: &ClientMessage) -> ServerMessage {
+    send_message(stream, message).await.unwrap();
+    next_reply(stream).await
+}
+
+pub async fn host_game(address: SocketAddr) -> (TcpStream, String) {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    match request(&mut stream, &ClientMessage::NewGame { map: None }).await {
+        ServerMessage::GameCreated { code, .. } => (stream, code),
+        reply => panic!("expected a new game, got {:?}", reply),
+    }
+}
+
+pub async fn join_game(address: SocketAddr, code: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let join = ClientMessage::JoinGame {
+        code: code.to_string(),
+    };
+    match request(&mut stream, &join).await {
+        ServerMessage::GameJoined { .. } => stream,
+        reply => panic!("expected to join {code}, got {:?}", reply),
+    }
+}
+
+// waits for a pushed snapshot that satisfies the check, keyframes only since nothing is acknowledged
+pub async fn snapshot_where<F>(stream: &mut TcpStream, check: F) -> Vec<Character>
+where
+    F: Fn(&[Character]) -> bool,
+{
+    loop {
+        if let ServerMessage::Snapshot { pieces, .. } = recv_message(stream).await.unwrap() {
+            if check(&pieces) {
+                return pieces;
+            }
+        }
+    }
+}
+
+// fills the host's side with barbarians, so every snapshot pushed from then on is a big one
+pub async fn crowd(stream: &mut TcpStream, count: u16) {
+    for i in 0..count {
+        let spawn = ClientMessage::SpawnUnit {
+            unit: "barbarian".to_string(),
+            row: (i % 9 + 1) as u8,
+        };
+        assert!(matches!(
+            request(stream, &spawn).await,
+            ServerMessage::SpawnAccepted
+        ));
+    }
+}
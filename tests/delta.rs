@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use fracas::{delta::*, net::*, terrain::*, utils::*, world::*, *};
+
+mod common;
+use common::*;
+
+fn battle(size: u16) -> Vec<Character> {
+    (0..size)
+        .map(|i| {
+            let colour = if i % 2 == 0 { Team::Green } else { Team::Red };
+            let mut piece = generate_barbarian((i % 9 + 1) as i16 * 2, colour);
+            piece.unique_id = i;
+            piece
+        })
+        .collect()
+}
+
+// roughly what one server tick does mid battle: a handful of units move, swing or get hit
+fn advance(pieces: &mut [Character], rng: &fastrand::Rng) {
+    for piece in pieces.iter_mut().filter(|p| p.hp > 0) {
+        piece.attack_cooldown = (piece.attack_cooldown - 1).max(0);
+        match rng.u8(0..20) {
+            0 => piece.x += 1,
+            1 => piece.hp -= rng.i16(1..7),
+            2 => piece.is_attacking = !piece.is_attacking,
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn apply_rebuilds_the_current_pieces() {
+    let rng = fastrand::Rng::with_seed(7);
+    let baseline = battle(50);
+    let mut current = baseline.clone();
+    advance(&mut current, &rng);
+    current.retain(|p| p.unique_id != 3);
+    let mut giant = generate_giant(4, Team::Red);
+    giant.unique_id = 50;
+    current.push(giant);
+
+    let delta = diff(&baseline, &current);
+    assert_eq!(delta.removed, vec![3]);
+    assert_eq!(delta.added.len(), 1);
+    assert_eq!(apply(&baseline, &delta), current);
+}
+
+#[test]
+fn unchanged_pieces_cost_nothing() {
+    let pieces = battle(200);
+    let delta = diff(&pieces, &pieces);
+    assert_eq!(delta, PiecesDelta::default());
+}
+
+#[test]
+fn encoder_and_decoder_stay_in_step() {
+    let rng = fastrand::Rng::with_seed(11);
+    let mut encoder = DeltaEncoder::default();
+    let mut decoder = DeltaDecoder::default();
+    let mut pieces = battle(100);
+    let mut acked = None;
+    let mut deltas = 0;
+
+    for tick in 1..=250 {
+        advance(&mut pieces, &rng);
+
+        let rebuilt =
+            match encoder.encode(acked, tick, Arc::new(pieces.clone()), &Terrain::default()) {
+                ServerMessage::Snapshot { tick, pieces, .. } => {
+                    decoder.keyframe(tick, pieces).to_vec()
+                }
+                ServerMessage::Delta {
+                    baseline,
+                    tick,
+                    delta,
+                } => {
+                    deltas += 1;
+                    decoder.delta(baseline, tick, &delta).unwrap().to_vec()
+                }
+                reply => panic!("unexpected {:?}", reply),
+            };
+        assert_eq!(rebuilt, pieces);
+
+        // the client only gets round to acknowledging every few ticks
+        if tick % 4 == 0 {
+            acked = decoder.latest_tick();
+        }
+    }
+
+    assert!(deltas > 200);
+}
+
+#[test]
+fn keyframes_are_sent_periodically() {
+    let mut encoder = DeltaEncoder::default();
+    let pieces = Arc::new(battle(10));
+
+    assert!(matches!(
+        encoder.encode(None, 98, pieces.clone(), &Terrain::default()),
+        ServerMessage::Snapshot { .. }
+    ));
+    assert!(matches!(
+        encoder.encode(Some(98), 99, pieces.clone(), &Terrain::default()),
+        ServerMessage::Delta { .. }
+    ));
+    assert!(matches!(
+        encoder.encode(
+            Some(99),
+            KEYFRAME_INTERVAL,
+            pieces.clone(),
+            &Terrain::default()
+        ),
+        ServerMessage::Snapshot { .. }
+    ));
+
+    // a baseline the encoder never sent can't be diffed against
+    assert!(matches!(
+        encoder.encode(Some(5), 101, pieces, &Terrain::default()),
+        ServerMessage::Snapshot { .. }
+    ));
+}
+
+#[test]
+fn deltas_are_far_smaller_than_full_snapshots() {
+    // a real battle, so the fallen leave the field and whoever is left keeps moving
+    let mut world = World::with_seed(3);
+    for i in 0..200 {
+        let team = if i % 2 == 0 { Team::Green } else { Team::Red };
+        world.spawn(generate_barbarian((i / 2 % 10) * 2, team));
+    }
+
+    // the pieces are all a snapshot repeats every tick, the terrain only comes with keyframes
+    let mut encoder = DeltaEncoder::default();
+    let mut full_bytes = 0;
+    let mut delta_bytes = 0;
+    for tick in 1..=KEYFRAME_INTERVAL * 3 {
+        world.step();
+        full_bytes += bincode::serialize(world.pieces()).unwrap().len();
+
+        let acked = Some(tick - 1).filter(|t| *t > 0);
+        delta_bytes += bincode::serialize(&encoder.encode(
+            acked,
+            tick,
+            Arc::new(world.pieces().to_vec()),
+            world.terrain(),
+        ))
+        .unwrap()
+        .len();
+    }
+
+    assert!(delta_bytes * 3 < full_bytes);
+}
+
+#[test]
+fn the_fallen_are_sent_as_removed() {
+    let mut world = World::with_seed(42);
+    world.spawn(generate_giant(10, Team::Green));
+    world.spawn(generate_archer(10, Team::Red));
+
+    let mut encoder = DeltaEncoder::default();
+    let mut decoder = DeltaDecoder::default();
+    let mut acked = None;
+    let mut removed = Vec::new();
+    while removed.is_empty() {
+        world.step();
+        assert!(world.tick() < 100_000, "nobody ever fell");
+
+        let pieces = Arc::new(world.pieces().to_vec());
+        match encoder.encode(acked, world.tick(), pieces, world.terrain()) {
+            ServerMessage::Snapshot { tick, pieces, .. } => {
+                decoder.keyframe(tick, pieces);
+            }
+            ServerMessage::Delta {
+                baseline,
+                tick,
+                delta,
+            } => {
+                removed = delta.removed.clone();
+                decoder.delta(baseline, tick, &delta).unwrap();
+            }
+            reply => panic!("unexpected {:?}", reply),
+        }
+        acked = decoder.latest_tick();
+    }
+
+    assert_eq!(removed, [1]);
+    let ids: Vec<u16> = world.pieces().iter().map(|p| p.unique_id).collect();
+    assert_eq!(ids, [0]);
+}
+
+#[async_std::test]
+async fn server_switches_to_deltas_once_acknowledged() {
+    let address = start_server().await;
+    let (mut stream, code) = host_game(address).await;
+    let mut opponent = join_game(address, &code).await;
+
+    let archer = |row| ClientMessage::SpawnUnit {
+        unit: "archer".to_string(),
+        row,
+    };
+    assert!(matches!(
+        request(&mut stream, &archer(3)).await,
+        ServerMessage::SpawnAccepted
+    ));
+    assert!(matches!(
+        request(&mut opponent, &archer(3)).await,
+        ServerMessage::SpawnAccepted
+    ));
+    assert!(matches!(
+        request(&mut opponent, &archer(7)).await,
+        ServerMessage::SpawnAccepted
+    ));
+
+    let mut decoder = DeltaDecoder::default();
+    let mut deltas = 0;
+    while deltas < 20 {
+        let count = match recv_message(&mut stream).await.unwrap() {
+            ServerMessage::Snapshot { tick, pieces, .. } => decoder.keyframe(tick, pieces).len(),
+            ServerMessage::Delta {
+                baseline,
+                tick,
+                delta,
+            } => {
+                deltas += 1;
+                decoder.delta(baseline, tick, &delta).unwrap().len()
+            }
+            _ => continue,
+        };
+        assert_eq!(count, 3);
+
+        let tick = decoder.latest_tick().unwrap();
+        send_message(&mut stream, &ClientMessage::Acknowledge { tick })
+            .await
+            .unwrap();
+    }
+
+    send_message(&mut stream, &ClientMessage::RequestSnapshot)
+        .await
+        .unwrap();
+    let pieces = snapshot_where(&mut stream, |p| p.len() == 3).await;
+    let ids: Vec<u16> = pieces.iter().map(|p| p.unique_id).collect();
+    assert_eq!(ids, vec![0, 1, 2]);
+}
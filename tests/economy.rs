@@ -0,0 +1,120 @@
+use async_std::net::TcpStream;
+
+use fracas::{catalogue::*, net::*, server::*, world::*, *};
+
+mod common;
+use common::*;
+
+fn spawn(unit: &str) -> ClientMessage {
+    ClientMessage::SpawnUnit {
+        unit: unit.to_string(),
+        row: 1,
+    }
+}
+
+// the next gold the server pushes that passes the check, skipping anything else
+async fn gold_where<F>(stream: &mut TcpStream, check: F) -> (u32, u32)
+where
+    F: Fn(u32) -> bool,
+{
+    loop {
+        if let ServerMessage::Gold { gold, income } = recv_message(stream).await.unwrap() {
+            if check(gold) {
+                return (gold, income);
+            }
+        }
+    }
+}
+
+#[test]
+fn every_unit_has_a_price() {
+    let catalogue = Catalogue::built_in();
+    let prices: Vec<(&str, u32)> = catalogue
+        .units
+        .iter()
+        .map(|u| (u.name.as_str(), u.cost))
+        .collect();
+    assert_eq!(prices, [("barbarian", 100), ("archer", 75), ("giant", 250)]);
+}
+
+#[async_std::test]
+async fn spawns_are_paid_for_and_refused_when_you_cannot() {
+    let address = start_server().await;
+    let (mut host, _) = host_game(address).await;
+
+    assert_eq!(gold_where(&mut host, |_| true).await, (300, 50));
+
+    assert!(matches!(
+        request(&mut host, &spawn("giant")).await,
+        ServerMessage::SpawnAccepted
+    ));
+    assert_eq!(gold_where(&mut host, |_| true).await, (50, 50));
+
+    // the lobby pays nothing, so that is all there is until the battle starts
+    for unit in ["barbarian", "archer"] {
+        let cost = Catalogue::built_in().unit(unit).unwrap().cost;
+        match request(&mut host, &spawn(unit)).await {
+            ServerMessage::Error(ServerError::NotEnoughGold { cost: c, gold }) => {
+                assert_eq!((c, gold), (cost, 50));
+            }
+            reply => panic!("expected {unit} to be too dear, got {:?}", reply),
+        }
+    }
+
+    // gold is the last thing checked, after anything that would refuse the spawn whatever the price
+    assert!(matches!(
+        request(&mut host, &spawn("dragon")).await,
+        ServerMessage::Error(ServerError::UnknownUnit(_))
+    ));
+}
+
+#[async_std::test]
+async fn every_player_keeps_their_own_purse() {
+    let address = start_server().await;
+    let (mut host, code) = host_game(address).await;
+    let mut guest = join_game(address, &code).await;
+
+    assert!(matches!(
+        request(&mut guest, &spawn("giant")).await,
+        ServerMessage::SpawnAccepted
+    ));
+    assert_eq!(gold_where(&mut guest, |g| g < 300).await.0, 50);
+    assert!(matches!(
+        request(&mut host, &spawn("giant")).await,
+        ServerMessage::SpawnAccepted
+    ));
+}
+
+#[async_std::test]
+async fn income_comes_in_while_the_battle_runs_and_a_rematch_starts_afresh() {
+    let config = ServerConfig {
+        countdown_secs: 0,
+        starting_gold: 0,
+        income: 1_000,
+        win_condition: WinCondition::TimeLimit(30),
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+    let (mut host, _) = host_game(address).await;
+
+    assert_eq!(gold_where(&mut host, |_| true).await, (0, 1_000));
+    assert!(matches!(
+        request(&mut host, &spawn("archer")).await,
+        ServerMessage::Error(ServerError::NotEnoughGold { cost: 75, gold: 0 })
+    ));
+
+    // a thousand a second at a hundred ticks a second is ten a tick, for the thirty ticks the battle lasts
+    send_message(&mut host, &ClientMessage::Ready)
+        .await
+        .unwrap();
+    assert_eq!(gold_where(&mut host, |g| g >= 300).await.0, 300);
+
+    send_message(&mut host, &ClientMessage::Rematch)
+        .await
+        .unwrap();
+    assert_eq!(gold_where(&mut host, |g| g < 300).await.0, 0);
+    assert!(matches!(
+        request(&mut host, &spawn("archer")).await,
+        ServerMessage::Error(ServerError::NotEnoughGold { cost: 75, gold: 0 })
+    ));
+}
@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use fracas::{editor::*, map::*, terrain::*, Team};
+
+fn scratch(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fracas-editor-{test}-{}", fastrand::u64(..)));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn blank() -> MapEditor {
+    MapEditor::new(Map::blank("sketch", 12, 6), Path::new("sketch.ron"))
+}
+
+#[test]
+fn lines_and_rectangles_wait_for_their_second_corner() {
+    let mut editor = blank();
+
+    editor.brush = Tile::Forest;
+    editor.set_cursor(3, 2);
+    editor.paint();
+    assert_eq!(editor.map.tiles.tile(3, 2), Tile::Forest);
+    assert!(editor.dirty);
+
+    editor.brush = Tile::Wall;
+    editor.set_tool(Tool::Line);
+    editor.set_cursor(0, 0);
+    editor.paint();
+    assert_eq!(editor.anchor, Some((0, 0)));
+    assert_eq!(editor.map.tiles.tile(0, 0), Tile::Open);
+    editor.move_cursor(4, 0);
+    assert_eq!(editor.preview().len(), 5);
+    editor.paint();
+    assert_eq!(editor.anchor, None);
+    assert_eq!(editor.map.tiles.rows()[0], "#####.......");
+
+    editor.brush = Tile::Water;
+    editor.set_tool(Tool::Rect);
+    editor.set_cursor(10, 5);
+    editor.paint();
+    editor.set_cursor(8, 4);
+    editor.paint();
+    assert_eq!(editor.map.tiles.rows()[4], "........~~~.");
+    assert_eq!(editor.map.tiles.rows()[5], "........~~~.");
+
+    // the cursor never leaves the field
+    editor.move_cursor(50, -50);
+    assert_eq!(editor.cursor, (11, 0));
+
+    editor.set_cursor(9, 5);
+    editor.pick();
+    assert_eq!(editor.brush, Tile::Water);
+}
+
+#[test]
+fn spawn_points_go_down_in_key_order() {
+    let mut editor = blank();
+    editor.map.tiles.set(1, 3, Tile::Wall);
+
+    for y in [0, 2, 4] {
+        editor.set_cursor(1, y);
+        assert!(editor.toggle_spawn());
+    }
+    assert_eq!(editor.map.spawn_point(Team::Green, 3), Some((1, 4)));
+
+    // taking one away moves the rest up a key
+    editor.set_cursor(1, 0);
+    assert!(editor.toggle_spawn());
+    assert_eq!(editor.map.spawn_points(Team::Green), &[(1, 2), (1, 4)]);
+
+    // not on a wall, and not where the other side spawns
+    editor.set_cursor(1, 3);
+    assert!(!editor.toggle_spawn());
+    editor.switch_team();
+    assert_eq!(editor.team, Team::Red);
+    editor.set_cursor(1, 2);
+    assert!(!editor.toggle_spawn());
+    assert!(editor.map.spawn_points(Team::Red).is_empty());
+}
+
+#[test]
+fn mirroring_evens_out_the_sides() {
+    let mut editor = blank();
+    editor.map.tiles.set(2, 1, Tile::Forest);
+    editor.map.tiles.set(9, 4, Tile::Road);
+    editor.set_cursor(1, 3);
+    editor.toggle_spawn();
+    assert_eq!(
+        editor.map.asymmetries(),
+        [(2, 1), (9, 1), (1, 3), (2, 4), (9, 4)]
+    );
+
+    // the half under the cursor wins
+    editor.mirror();
+    assert_eq!(editor.map.asymmetries(), []);
+    assert_eq!(editor.map.tiles.tile(9, 1), Tile::Forest);
+    assert_eq!(editor.map.tiles.tile(9, 4), Tile::Open);
+    assert_eq!(editor.map.spawn_points(Team::Red), &[(10, 3)]);
+
+    for map in Map::load_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("maps")).unwrap() {
+        assert_eq!(map.asymmetries(), [], "{} is lopsided", map.name);
+    }
+}
+
+#[test]
+fn maps_are_saved_only_once_they_would_load() {
+    let dir = scratch("save");
+    let path = dir.join("duel.ron");
+
+    // nothing there yet, so a blank map named after the file
+    let mut editor = MapEditor::open(&path, 20, 8).unwrap();
+    assert_eq!(editor.map.name, "duel");
+    assert_eq!((editor.map.width, editor.map.height), (20, 8));
+
+    assert!(matches!(editor.save(), Err(MapError::Invalid(_))));
+    assert!(!path.exists());
+
+    editor.set_cursor(0, 4);
+    editor.toggle_spawn();
+    editor.brush = Tile::Wall;
+    editor.set_tool(Tool::Line);
+    editor.set_cursor(9, 0);
+    editor.paint();
+    editor.set_cursor(9, 7);
+    editor.paint();
+    editor.mirror();
+    editor.save().unwrap();
+    assert!(!editor.dirty);
+
+    let reopened = MapEditor::open(&path, 20, 8).unwrap();
+    assert_eq!(reopened.map, editor.map);
+    assert_eq!(reopened.map.spawn_point(Team::Red, 1), Some((19, 4)));
+    assert!(!reopened.dirty);
+
+    std::fs::write(&path, "(name: \"duel\")").unwrap();
+    assert!(matches!(
+        MapEditor::open(&path, 20, 8),
+        Err(MapError::Parse(_))
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,300 @@
+use async_std::net::TcpStream;
+
+use fracas::{events::*, net::*, server::*, utils::*, world::*, *};
+
+mod common;
+use common::*;
+
+fn battle(seed: u64) -> (World, Vec<CombatEvent>) {
+    let mut world = World::with_seed(seed);
+    world.spawn(generate_giant(10, Team::Green));
+    world.spawn(generate_archer(12, Team::Red));
+    world.spawn(generate_barbarian(8, Team::Red));
+
+    let mut events = world.take_events();
+    while world.outcome(WinCondition::LastTeamStanding).is_none() {
+        world.step();
+        events.extend(world.take_events());
+        assert!(world.tick() < 100_000, "the battle never finished");
+    }
+    (world, events)
+}
+
+#[test]
+fn every_attack_is_started_rolled_and_settled() {
+    let (world, events) = battle(3);
+
+    assert!(events[..3].iter().all(|e| e.kind() == EventKind::Spawned));
+    assert_eq!(events[0].actor().team, Team::Green);
+
+    let attacks: Vec<&[CombatEvent]> = events
+        .split(|e| e.kind() == EventKind::AttackStarted)
+        .skip(1)
+        .collect();
+    assert!(!attacks.is_empty());
+    for attack in attacks {
+        let (roll, defence) = match &attack[0] {
+            CombatEvent::Rolled { roll, defence, .. } => (*roll, *defence),
+            other => panic!("expected a roll, got {other}"),
+        };
+        match &attack[1] {
+            CombatEvent::Hit { hp_left, .. } => {
+                assert!(roll >= defence);
+                assert_eq!(
+                    attack.get(2).map(|e| e.kind()) == Some(EventKind::Killed),
+                    *hp_left <= 0
+                );
+            }
+            CombatEvent::Miss { .. } => assert!(roll < defence),
+            other => panic!("expected a hit or a miss, got {other}"),
+        }
+    }
+
+    // the events tell the whole story, down to what everyone has left
+    for piece in world.pieces() {
+        let last_hit = events.iter().rev().find_map(|e| match e {
+            CombatEvent::Hit {
+                target, hp_left, ..
+            } if target.unique_id == piece.unique_id => Some(*hp_left),
+            _ => None,
+        });
+        assert_eq!(last_hit.unwrap_or(piece.hp), piece.hp);
+    }
+    let killed = events.iter().filter(|e| e.kind() == EventKind::Killed);
+    let spawned = events.iter().filter(|e| e.kind() == EventKind::Spawned);
+    assert_eq!(killed.count(), spawned.count() - world.living().count());
+}
+
+#[test]
+fn moves_are_one_step_at_a_time() {
+    let (_, events) = battle(4);
+
+    let moves: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            CombatEvent::Moved { from, to, .. } => Some((*from, *to)),
+            _ => None,
+        })
+        .collect();
+    assert!(!moves.is_empty());
+    assert!(moves
+        .iter()
+        .all(|(from, to)| (from.0 - to.0).abs() <= 1 && (from.1 - to.1).abs() <= 1));
+}
+
+#[test]
+fn the_text_log_leaves_out_movement() {
+    let (_, events) = battle(5);
+
+    let mut log = TextLog::default();
+    log.record(7, &events);
+    let lines = log.take();
+
+    let moves = events.iter().filter(|e| e.kind() == EventKind::Moved);
+    assert_eq!(lines.len(), events.len() - moves.count());
+    assert!(lines[0].starts_with("     7  G0 joins at "));
+    assert!(lines.iter().any(|l| l.contains(" rolled to attack: ")));
+    assert!(log.take().is_empty());
+}
+
+async fn next_events(stream: &mut TcpStream) -> (u64, Vec<CombatEvent>) {
+    loop {
+        if let ServerMessage::Events { tick, events } = recv_message(stream).await.unwrap() {
+            return (tick, events);
+        }
+    }
+}
+
+#[async_std::test]
+async fn everyone_in_the_session_hears_about_a_spawn() {
+    let address = start_server().await;
+    let (mut host, code) = host_game(address).await;
+    let mut guest = join_game(address, &code).await;
+
+    let spawn = ClientMessage::SpawnUnit {
+        unit: "giant".to_string(),
+        row: 3,
+    };
+    assert!(matches!(
+        request(&mut guest, &spawn).await,
+        ServerMessage::SpawnAccepted
+    ));
+
+    for stream in [&mut host, &mut guest] {
+        let (tick, events) = next_events(stream).await;
+        assert_eq!(tick, 0);
+        match &events[..] {
+            [CombatEvent::Spawned { unit, hp, .. }] => {
+                assert_eq!((unit.denotation, unit.team), ('G', Team::Red));
+                assert_eq!(*hp, 30);
+            }
+            other => panic!("expected a spawn, got {:?}", other),
+        }
+    }
+}
+
+#[async_std::test]
+async fn players_hear_about_the_fighting_but_not_the_marching() {
+    let config = ServerConfig {
+        countdown_secs: 0,
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+    let (mut host, code) = host_game(address).await;
+    let mut guest = join_game(address, &code).await;
+
+    // face to face on the same row, so they are fighting soon after the start
+    for stream in [&mut host, &mut guest] {
+        let spawn = ClientMessage::SpawnUnit {
+            unit: "barbarian".to_string(),
+            row: 5,
+        };
+        request(stream, &spawn).await;
+        send_message(stream, &ClientMessage::Ready).await.unwrap();
+    }
+
+    loop {
+        let (_, events) = next_events(&mut host).await;
+        assert!(events.iter().all(|e| e.kind() != EventKind::Moved));
+        if events.iter().any(|e| e.kind() == EventKind::AttackStarted) {
+            break;
+        }
+    }
+}
+
+fn fighter(unique_id: u16, denotation: char, team: Team) -> Fighter {
+    Fighter {
+        unique_id,
+        denotation,
+        team,
+    }
+}
+
+fn hit(attacker: Fighter, target: Fighter) -> CombatEvent {
+    CombatEvent::Hit {
+        attacker,
+        target,
+        damage: 2,
+        hp_left: 5,
+    }
+}
+
+#[test]
+fn the_combat_log_keeps_only_the_newest_events() {
+    let (green, red) = (fighter(0, 'B', Team::Green), fighter(1, 'A', Team::Red));
+    let mut log = CombatLog::new(3);
+
+    log.record(
+        1,
+        &[
+            CombatEvent::Moved {
+                unit: green,
+                from: (1, 1),
+                to: (2, 1),
+            },
+            hit(green, red),
+            CombatEvent::Miss {
+                attacker: red,
+                target: green,
+            },
+        ],
+    );
+    assert_eq!(log.len(), 2);
+
+    log.record(2, &[hit(red, green), hit(green, red)]);
+    let ticks: Vec<u64> = log.page(10).iter().map(|(tick, _)| *tick).collect();
+    assert_eq!(ticks, [1, 2, 2]);
+    assert_eq!(log.page(10)[0].1.kind(), EventKind::Miss);
+
+    log.clear();
+    assert!(log.is_empty());
+}
+
+#[test]
+fn the_combat_log_filters_by_team_unit_and_kind() {
+    let barbarian = fighter(0, 'B', Team::Green);
+    let archer = fighter(1, 'A', Team::Red);
+    let giant = fighter(2, 'G', Team::Red);
+    let mut log = CombatLog::new(100);
+    log.record(
+        4,
+        &[
+            hit(barbarian, archer),
+            hit(giant, barbarian),
+            CombatEvent::Killed {
+                attacker: barbarian,
+                target: archer,
+            },
+            CombatEvent::Miss {
+                attacker: archer,
+                target: barbarian,
+            },
+        ],
+    );
+
+    let shown = |log: &CombatLog| log.matching().count();
+    assert_eq!(shown(&log), 4);
+
+    log.set_filter(LogFilter {
+        team: Some(Team::Red),
+        ..LogFilter::default()
+    });
+    assert_eq!(shown(&log), 2);
+
+    // a unit is in an event whichever end of it it is on
+    log.set_filter(LogFilter {
+        unit: Some('A'),
+        ..LogFilter::default()
+    });
+    assert_eq!(shown(&log), 3);
+
+    log.set_filter(LogFilter {
+        kind: Some(EventKind::Hit),
+        ..LogFilter::default()
+    });
+    assert_eq!(shown(&log), 2);
+
+    log.set_filter(LogFilter {
+        team: Some(Team::Green),
+        unit: Some('A'),
+        kind: Some(EventKind::Killed),
+    });
+    assert_eq!(shown(&log), 1);
+}
+
+#[test]
+fn the_combat_log_pages_back_and_holds_still_while_reading() {
+    let (green, red) = (fighter(0, 'B', Team::Green), fighter(1, 'A', Team::Red));
+    let mut log = CombatLog::new(100);
+    for tick in 0..25 {
+        log.record(tick, &[hit(green, red)]);
+    }
+
+    let first_tick = |log: &CombatLog| log.page(10)[0].0;
+    assert_eq!(first_tick(&log), 15);
+
+    log.page_up(10);
+    assert_eq!((log.scroll(), first_tick(&log)), (10, 5));
+
+    // no further back than a full page of the oldest lines
+    log.page_up(10);
+    assert_eq!((log.scroll(), first_tick(&log)), (15, 0));
+
+    // newer lines arriving leave the view where it is
+    log.record(25, &[hit(green, red), hit(red, green)]);
+    assert_eq!((log.scroll(), first_tick(&log)), (17, 0));
+
+    log.page_down(10);
+    assert_eq!(first_tick(&log), 10);
+    log.scroll_to_end();
+    assert_eq!(log.page(10).last().unwrap().0, 25);
+
+    // and a new filter starts from the newest lines again
+    log.page_up(10);
+    log.set_filter(LogFilter {
+        team: Some(Team::Red),
+        ..LogFilter::default()
+    });
+    assert_eq!(log.scroll(), 0);
+    assert_eq!(log.page(10).len(), 1);
+}
@@ -0,0 +1,218 @@
+use std::time::Duration;
+
+use async_std::{future::timeout, net::TcpStream, task::sleep};
+
+use fracas::{net::*, server::*, world::*, *};
+
+mod common;
+use common::*;
+
+fn barbarian(row: u8) -> ClientMessage {
+    ClientMessage::SpawnUnit {
+        unit: "barbarian".to_string(),
+        row,
+    }
+}
+
+async fn next_phase(stream: &mut TcpStream) -> MatchPhase {
+    match next_reply(stream).await {
+        ServerMessage::Phase(phase) => phase,
+        reply => panic!("expected the match to move on, got {:?}", reply),
+    }
+}
+
+// no countdown, and a battle short enough that the armies never meet
+fn quick_rules() -> ServerConfig {
+    ServerConfig {
+        countdown_secs: 0,
+        win_condition: WinCondition::TimeLimit(20),
+        ..ServerConfig::default()
+    }
+}
+
+#[async_std::test]
+async fn a_match_runs_from_lobby_to_rematch() {
+    let address = start_server_with(quick_rules()).await;
+    let (mut host, code) = host_game(address).await;
+    let mut joiner = join_game(address, &code).await;
+
+    request(&mut host, &barbarian(1)).await;
+    request(&mut host, &barbarian(2)).await;
+    request(&mut joiner, &barbarian(9)).await;
+
+    // nobody is ready yet, so the placed units stay exactly where they are
+    let placed = snapshot_where(&mut host, |p| p.len() == 3).await;
+    for _ in 0..10 {
+        assert_eq!(snapshot_where(&mut host, |_| true).await, placed);
+    }
+
+    send_message(&mut host, &ClientMessage::Ready)
+        .await
+        .unwrap();
+    send_message(&mut joiner, &ClientMessage::Ready)
+        .await
+        .unwrap();
+
+    for stream in [&mut host, &mut joiner] {
+        assert_eq!(
+            next_phase(stream).await,
+            MatchPhase::Countdown { seconds_left: 0 }
+        );
+        assert_eq!(next_phase(stream).await, MatchPhase::Running);
+        assert_eq!(
+            next_phase(stream).await,
+            MatchPhase::Finished(Outcome::Won(Team::Green))
+        );
+    }
+
+    // the battle is over, nothing more can be added to it
+    let finished = MatchPhase::Finished(Outcome::Won(Team::Green));
+    for (stream, message) in [
+        (&mut host, barbarian(5)),
+        (&mut joiner, ClientMessage::Ready),
+    ] {
+        assert!(matches!(
+            request(stream, &message).await,
+            ServerMessage::Error(ServerError::WrongPhase(phase)) if phase == finished
+        ));
+    }
+
+    send_message(&mut joiner, &ClientMessage::Rematch)
+        .await
+        .unwrap();
+    for stream in [&mut host, &mut joiner] {
+        assert_eq!(next_phase(stream).await, MatchPhase::Lobby);
+        snapshot_where(stream, |p| p.is_empty()).await;
+    }
+}
+
+#[async_std::test]
+async fn a_player_who_falls_behind_still_sees_every_phase() {
+    let config = ServerConfig {
+        tick_rate: 200,
+        starting_gold: 300 * 100,
+        win_condition: WinCondition::TimeLimit(200),
+        ..quick_rules()
+    };
+    let address = start_server_with(config).await;
+    let (mut host, code) = host_game(address).await;
+    let mut joiner = join_game(address, &code).await;
+    crowd(&mut host, 300).await;
+
+    // with nobody on red the whole battle goes by while the joiner reads nothing and snapshots of the crowd pile up
+    send_message(&mut host, &ClientMessage::Ready)
+        .await
+        .unwrap();
+    send_message(&mut joiner, &ClientMessage::Ready)
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(3)).await;
+
+    let phases = async {
+        let mut phases = Vec::new();
+        while !matches!(phases.last(), Some(MatchPhase::Finished(_))) {
+            phases.push(next_phase(&mut joiner).await);
+        }
+        phases
+    };
+    let phases = timeout(Duration::from_secs(10), phases)
+        .await
+        .expect("a phase change was dropped");
+    assert_eq!(
+        phases[..2],
+        [
+            MatchPhase::Countdown { seconds_left: 0 },
+            MatchPhase::Running
+        ]
+    );
+    assert_eq!(phases.len(), 3);
+}
+
+#[async_std::test]
+async fn joiners_are_told_the_current_phase() {
+    let address = start_server_with(quick_rules()).await;
+    let (mut host, code) = host_game(address).await;
+    request(&mut host, &barbarian(1)).await;
+
+    // a lone ready player is enough to start, and with nobody on red it runs out the clock
+    send_message(&mut host, &ClientMessage::Ready)
+        .await
+        .unwrap();
+    while !matches!(next_phase(&mut host).await, MatchPhase::Finished(_)) {}
+
+    let mut late = TcpStream::connect(address).await.unwrap();
+    let join = ClientMessage::JoinGame { code };
+    assert!(matches!(
+        request(&mut late, &join).await,
+        ServerMessage::GameJoined {
+            phase: MatchPhase::Finished(Outcome::Won(Team::Green)),
+            ..
+        }
+    ));
+}
+
+#[async_std::test]
+async fn the_countdown_ticks_down_in_seconds() {
+    let config = ServerConfig {
+        countdown_secs: 2,
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+    let (mut host, _) = host_game(address).await;
+
+    send_message(&mut host, &ClientMessage::Ready)
+        .await
+        .unwrap();
+    assert_eq!(
+        next_phase(&mut host).await,
+        MatchPhase::Countdown { seconds_left: 2 }
+    );
+    assert_eq!(
+        next_phase(&mut host).await,
+        MatchPhase::Countdown { seconds_left: 1 }
+    );
+    assert_eq!(next_phase(&mut host).await, MatchPhase::Running);
+}
+
+#[async_std::test]
+async fn lifecycle_requests_are_checked_against_the_phase() {
+    let address = start_server().await;
+
+    let mut stranger = TcpStream::connect(address).await.unwrap();
+    for message in [ClientMessage::Ready, ClientMessage::Rematch] {
+        assert!(matches!(
+            request(&mut stranger, &message).await,
+            ServerMessage::Error(ServerError::NotInSession)
+        ));
+    }
+
+    let (mut host, _) = host_game(address).await;
+    assert!(matches!(
+        request(&mut host, &ClientMessage::Rematch).await,
+        ServerMessage::Error(ServerError::WrongPhase(MatchPhase::Lobby))
+    ));
+}
+
+#[async_std::test]
+async fn a_log_that_cannot_be_opened_does_not_stop_the_battles() {
+    let listener = async_std::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let address = listener.local_addr().unwrap();
+    let config = ServerConfig {
+        log_path: std::env::temp_dir().join("fracas-no-such-dir/fracas.log"),
+        ..ServerConfig::default()
+    };
+    async_std::task::spawn(server(listener, config));
+
+    // every spawn is logged, and the field keeps coming after each one
+    let battle = async {
+        let (mut host, _) = host_game(address).await;
+        for count in 1..=2 {
+            request(&mut host, &barbarian(count as u8)).await;
+            snapshot_where(&mut host, |p| p.len() == count).await;
+            sleep(Duration::from_millis(50)).await;
+        }
+    };
+    assert!(timeout(Duration::from_secs(5), battle).await.is_ok());
+}
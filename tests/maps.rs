@@ -0,0 +1,198 @@
+use std::path::{Path, PathBuf};
+
+use async_std::net::TcpStream;
+
+use fracas::{catalogue::*, map::*, replay::*, server::*, terrain::*, world::*, *};
+
+mod common;
+use common::*;
+
+fn maps_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("maps")
+}
+
+fn pit() -> Map {
+    Map::load(&maps_dir().join("pit.ron")).unwrap()
+}
+
+fn invalid(map: &Map) -> String {
+    match Map::parse(&ron::to_string(map).unwrap()) {
+        Err(MapError::Invalid(reason)) => reason,
+        other => panic!("expected {} to be refused, got {:?}", map.name, other),
+    }
+}
+
+#[test]
+fn the_built_in_map_is_the_classic_field() {
+    let map = Map::built_in();
+
+    assert_eq!(map.name, "classic");
+    assert_eq!((map.width, map.height), (FIELD_WIDTH, FIELD_HEIGHT));
+    assert_eq!(map.tiles, Terrain::default());
+    assert!(map.objectives.is_empty());
+
+    // the nine rows two apart, at either end of the field
+    let rows: Vec<(i16, i16)> = (1..=9).map(|row| (1, row * 2)).collect();
+    assert_eq!(map.spawn_points(Team::Green), &rows[..]);
+    assert_eq!(map.spawn_point(Team::Red, 3), Some((68, 6)));
+    assert_eq!(map.spawn_point(Team::Red, 0), None);
+    assert_eq!(map.spawn_point(Team::Red, 10), None);
+}
+
+#[test]
+fn every_shipped_map_loads() {
+    let maps = Map::load_dir(&maps_dir()).unwrap();
+    let names: Vec<&str> = maps.iter().map(|m| m.name.as_str()).collect();
+
+    // the built in map comes first, its file only stands in for it
+    assert_eq!(names, ["classic", "pit", "river", "ruins"]);
+    assert_eq!(&maps[0], Map::built_in());
+
+    let pit = &maps[1];
+    assert_eq!((pit.width, pit.height), (40, 12));
+    assert_eq!(pit.spawn_points(Team::Red).len(), 5);
+    assert!(maps[2].objectives.iter().any(|o| o.name == "ford"));
+}
+
+#[test]
+fn broken_maps_are_refused() {
+    assert!(matches!(
+        Map::parse("(name: \"x\")"),
+        Err(MapError::Parse(_))
+    ));
+    assert!(matches!(
+        Map::load(Path::new("no-such-map.ron")),
+        Err(MapError::Io(_))
+    ));
+
+    let mut wrong_size = pit();
+    wrong_size.width = 41;
+    assert!(invalid(&wrong_size).contains("40x12"));
+
+    let mut too_big = pit();
+    too_big.tiles = Terrain::open(MAX_WIDTH + 1, 12);
+    too_big.width = MAX_WIDTH + 1;
+    assert!(invalid(&too_big).contains("maps go up to"));
+
+    let mut walled_in = pit();
+    walled_in.tiles.set(1, 4, Tile::Wall);
+    assert!(invalid(&walled_in).contains("blocked"));
+
+    let mut one_sided = pit();
+    one_sided.spawns.retain(|zone| zone.team == Team::Green);
+    assert!(invalid(&one_sided).contains("Red"));
+
+    let mut crowded = pit();
+    crowded.spawns[0].points = (0..10).map(|y| (1, y)).collect();
+    assert!(invalid(&crowded).contains("1 to 9"));
+
+    let mut shared = pit();
+    shared.spawns[1].points[0] = shared.spawns[0].points[0];
+    assert!(invalid(&shared).contains("two spawns"));
+
+    let mut lost = pit();
+    lost.objectives.push(Objective {
+        name: "hill".to_string(),
+        x: 40,
+        y: 0,
+    });
+    assert!(invalid(&lost).contains("off the field"));
+}
+
+#[test]
+fn a_clashing_name_in_a_maps_directory_names_the_file() {
+    let dir = std::env::temp_dir().join(format!("fracas-maps-{}", fastrand::u64(..)));
+    std::fs::create_dir_all(&dir).unwrap();
+    let text = std::fs::read_to_string(maps_dir().join("pit.ron")).unwrap();
+    std::fs::write(dir.join("a.ron"), &text).unwrap();
+    std::fs::write(dir.join("b.ron"), &text).unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a map").unwrap();
+
+    match Map::load_dir(&dir) {
+        Err((path, MapError::Invalid(reason))) => {
+            assert_eq!(path, dir.join("b.ron"));
+            assert!(reason.contains("pit"));
+        }
+        other => panic!("expected the second pit to be refused, got {:?}", other),
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[async_std::test]
+async fn the_host_picks_the_map() {
+    let config = ServerConfig {
+        maps: Map::load_dir(&maps_dir()).unwrap(),
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+
+    // no choice gets the first map
+    let (_, code) = host_game(address).await;
+    let mut guest = TcpStream::connect(address).await.unwrap();
+    match request(&mut guest, &ClientMessage::JoinGame { code }).await {
+        ServerMessage::GameJoined { map, .. } => assert_eq!(&map, Map::built_in()),
+        reply => panic!("expected to join, got {:?}", reply),
+    }
+
+    let mut host = TcpStream::connect(address).await.unwrap();
+    let unknown = ClientMessage::NewGame {
+        map: Some("moon".to_string()),
+    };
+    match request(&mut host, &unknown).await {
+        ServerMessage::Error(ServerError::UnknownMap { name, available }) => {
+            assert_eq!(name, "moon");
+            assert_eq!(available, ["classic", "pit", "river", "ruins"]);
+        }
+        reply => panic!("expected moon to be unknown, got {:?}", reply),
+    }
+
+    let chosen = ClientMessage::NewGame {
+        map: Some("pit".to_string()),
+    };
+    match request(&mut host, &chosen).await {
+        ServerMessage::GameCreated { map, .. } => assert_eq!(map, pit()),
+        reply => panic!("expected a game on the pit, got {:?}", reply),
+    }
+
+    // the pit only has five spawn points a side
+    let spawn = |row| ClientMessage::SpawnUnit {
+        unit: "barbarian".to_string(),
+        row,
+    };
+    assert!(matches!(
+        request(&mut host, &spawn(6)).await,
+        ServerMessage::Error(ServerError::RowOutOfRange(6))
+    ));
+    assert!(matches!(
+        request(&mut host, &spawn(5)).await,
+        ServerMessage::SpawnAccepted
+    ));
+
+    let pieces = snapshot_where(&mut host, |p| !p.is_empty()).await;
+    assert_eq!((pieces[0].x, pieces[0].y), (1, 10));
+}
+
+#[test]
+fn replays_are_played_back_on_their_map() {
+    let mut replay = Replay::new(
+        3,
+        100,
+        WinCondition::LastTeamStanding,
+        Catalogue::built_in().clone(),
+        pit(),
+    );
+    replay.record_spawn(0, Team::Green, "giant", 2);
+    replay.record_spawn(0, Team::Red, "archer", 5);
+    replay.length = 10;
+
+    let replay = ron::from_str::<Replay>(&replay.to_ron()).unwrap();
+    let playback = Playback::new(replay);
+    assert_eq!(playback.world().terrain(), &pit().tiles);
+    let spots: Vec<(i16, i16)> = playback
+        .world()
+        .pieces()
+        .iter()
+        .map(|p| (p.x, p.y))
+        .collect();
+    assert_eq!(spots, [(1, 4), (38, 10)]);
+}
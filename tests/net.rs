@@ -0,0 +1,174 @@
+use std::io::ErrorKind;
+
+use async_std::{
+    net::{TcpListener, TcpStream},
+    task::spawn,
+};
+use futures::AsyncWriteExt;
+
+use fracas::{net::*, server::*, utils::*, *};
+
+mod common;
+use common::*;
+
+#[async_std::test]
+async fn large_snapshot_survives_a_real_socket() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let pieces: Vec<Character> = (0..500)
+        .map(|i| generate_barbarian((i % 9) * 2, Team::Green))
+        .collect();
+    let payload = bincode::serialize(&pieces).unwrap();
+    assert!(payload.len() > 1024 * 16);
+
+    let sender = spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        write_frame(&mut stream, &payload).await.unwrap();
+    });
+
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let received = read_frame(&mut stream).await.unwrap();
+    sender.await;
+
+    let received: Vec<Character> = bincode::deserialize(&received).unwrap();
+    assert_eq!(received.len(), 500);
+    assert!(received
+        .iter()
+        .zip(pieces.iter())
+        .all(|(a, b)| a.unique_id == b.unique_id && a.y == b.y));
+}
+
+#[async_std::test]
+async fn oversized_header_is_rejected() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let sender = spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let header = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes();
+        stream.write_all(&header).await.unwrap();
+        stream.flush().await.unwrap();
+    });
+
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let err = read_frame(&mut stream).await.unwrap_err();
+    sender.await;
+
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[async_std::test]
+async fn truncated_frame_is_an_error() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let sender = spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        stream.write_all(&100u32.to_be_bytes()).await.unwrap();
+        stream.write_all(&[0u8; 10]).await.unwrap();
+    });
+
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let err = read_frame(&mut stream).await.unwrap_err();
+    sender.await;
+
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[async_std::test]
+async fn receiving_stops_quietly_at_the_end_and_says_why_otherwise() {
+    let mut wire = Vec::new();
+    for message in [ClientMessage::Ready, ClientMessage::RequestSnapshot] {
+        send_message(&mut wire, &message).await.unwrap();
+    }
+    let (pushed, mut received) = futures::channel::mpsc::unbounded();
+    receive_messages(futures::io::Cursor::new(wire.clone()), pushed)
+        .await
+        .unwrap();
+    assert_eq!(received.try_recv().ok(), Some(ClientMessage::Ready));
+    assert_eq!(
+        received.try_recv().ok(),
+        Some(ClientMessage::RequestSnapshot)
+    );
+
+    // everything before the bad frame still gets through
+    write_frame(&mut wire, &[0xff; 8]).await.unwrap();
+    let (pushed, mut received) = futures::channel::mpsc::unbounded::<ClientMessage>();
+    let err = receive_messages(futures::io::Cursor::new(wire), pushed)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(received.try_recv().ok(), Some(ClientMessage::Ready));
+}
+
+#[async_std::test]
+async fn server_snapshot_holds_hundreds_of_pieces() {
+    let config = ServerConfig {
+        starting_gold: 300 * 100,
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+    let (mut stream, _) = host_game(address).await;
+
+    // one team only, so nothing moves or fights while we fill the board
+    for i in 0..300u16 {
+        let spawn = ClientMessage::SpawnUnit {
+            unit: "barbarian".to_string(),
+            row: (i % 9 + 1) as u8,
+        };
+        assert!(matches!(
+            request(&mut stream, &spawn).await,
+            ServerMessage::SpawnAccepted
+        ));
+    }
+
+    send_message(&mut stream, &ClientMessage::RequestSnapshot)
+        .await
+        .unwrap();
+    let pieces = snapshot_where(&mut stream, |p| p.len() == 300).await;
+    assert!(pieces.iter().all(|p| p.hp > 0 && p.team == Team::Green));
+}
+
+#[async_std::test]
+async fn server_pushes_snapshots_to_every_client() {
+    let address = start_server().await;
+    let (mut host, code) = host_game(address).await;
+    let mut watcher = join_game(address, &code).await;
+
+    let spawn = ClientMessage::SpawnUnit {
+        unit: "giant".to_string(),
+        row: 5,
+    };
+    assert!(matches!(
+        request(&mut host, &spawn).await,
+        ServerMessage::SpawnAccepted
+    ));
+
+    // the watcher never asks for anything, the spawn still reaches it within a few ticks
+    let pieces = snapshot_where(&mut watcher, |p| !p.is_empty()).await;
+    assert_eq!(pieces.len(), 1);
+    assert_eq!(pieces[0].denotation, 'G');
+    assert_eq!(pieces[0].y, 10);
+}
+
+#[async_std::test]
+async fn server_answers_garbage_with_a_typed_error() {
+    let address = start_server().await;
+
+    // a bad frame is answered and the connection stays usable afterwards
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    for garbage in [&b"update"[..], &b"gb3"[..], &[0xff; 64][..], &[][..]] {
+        write_frame(&mut stream, garbage).await.unwrap();
+        let reply = next_reply(&mut stream).await;
+        assert!(matches!(
+            reply,
+            ServerMessage::Error(ServerError::Malformed(_))
+        ));
+    }
+
+    assert!(matches!(
+        request(&mut stream, &ClientMessage::NewGame { map: None }).await,
+        ServerMessage::GameCreated { .. }
+    ));
+}
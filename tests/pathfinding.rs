@@ -0,0 +1,113 @@
+use fracas::{pathfinding::*, terrain::*, utils::*, world::*, Character, Team};
+
+// a unit that never gets round to moving, to stand in the way
+fn post(x: i16, y: i16, colour: Team) -> Character {
+    let mut piece = generate_giant(y, colour);
+    piece.x = x;
+    piece.movement_rate = i16::MAX;
+    piece.movement_cooldown = i16::MAX;
+    piece
+}
+
+fn runner(x: i16, y: i16) -> Character {
+    let mut piece = generate_barbarian(y, Team::Green);
+    piece.x = x;
+    piece.movement_rate = 1;
+    piece.movement_cooldown = 1;
+    piece
+}
+
+#[test]
+fn the_field_counts_steps_around_a_teams_own_units() {
+    let pieces = vec![
+        post(5, 5, Team::Red),
+        post(3, 4, Team::Green),
+        post(3, 5, Team::Green),
+        post(3, 6, Team::Green),
+    ];
+    let field = FlowField::towards_enemies_of(Team::Green, &pieces, &Terrain::default());
+
+    assert_eq!(field.distance(5, 5), Some(0));
+    assert_eq!(field.distance(4, 5), Some(1));
+    assert_eq!(field.distance(3, 5), None);
+    // no way straight through the wall, so the long way round
+    assert_eq!(field.distance(2, 5), Some(4));
+    assert_eq!(field.distance(-1, 5), None);
+    assert_eq!(field.distance(FIELD_WIDTH, 5), None);
+
+    // the other side only sees its own units as in the way
+    let field = FlowField::towards_enemies_of(Team::Red, &pieces, &Terrain::default());
+    assert_eq!(field.distance(3, 5), Some(0));
+    assert_eq!(field.distance(5, 5), None);
+}
+
+#[test]
+fn units_walk_round_a_friend_that_is_standing_still() {
+    let mut world = World::with_seed(7);
+    let id = world.spawn(runner(10, 10)).unwrap();
+    world.spawn(post(11, 10, Team::Green));
+    world.spawn(post(40, 10, Team::Red));
+
+    for _ in 0..10 {
+        world.step();
+    }
+
+    let piece = world.piece(id).unwrap();
+    assert!(piece.x > 11, "stuck at {},{}", piece.x, piece.y);
+}
+
+#[test]
+fn units_find_the_gap_in_a_wall() {
+    let mut world = World::with_seed(3);
+    let id = world.spawn(runner(5, 10)).unwrap();
+    for y in 0..FIELD_HEIGHT {
+        if y != 2 {
+            world.spawn(post(10, y, Team::Green));
+        }
+    }
+    // holds its swing so the runner is still there to be found at the end
+    let mut target = post(40, 10, Team::Red);
+    target.attack_cooldown = i16::MAX;
+    world.spawn(target);
+
+    for _ in 0..60 {
+        world.step();
+    }
+
+    let piece = world.piece(id).unwrap();
+    assert_eq!((piece.x, piece.y), (39, 10));
+}
+
+#[test]
+fn a_boxed_in_unit_waits_where_it_is() {
+    let mut world = World::with_seed(5);
+    let id = world.spawn(runner(10, 10)).unwrap();
+    for (dx, dy) in NEIGHBOURS {
+        world.spawn(post(10 + dx, 10 + dy, Team::Green));
+    }
+    world.spawn(post(40, 10, Team::Red));
+
+    for _ in 0..20 {
+        world.step();
+    }
+
+    let piece = world.piece(id).unwrap();
+    assert_eq!((piece.x, piece.y), (10, 10));
+    assert!(piece.movement_cooldown <= 0);
+}
+
+#[test]
+fn movement_keeps_to_the_units_pace() {
+    let mut world = World::with_seed(11);
+    let id = world.spawn(generate_barbarian(10, Team::Green)).unwrap();
+    world.spawn(post(40, 10, Team::Red));
+    let rate = world.piece(id).unwrap().movement_rate;
+
+    for _ in 0..rate * 4 {
+        world.step();
+    }
+
+    // straight along the row, one cell per movement_rate ticks
+    let piece = world.piece(id).unwrap();
+    assert_eq!((piece.x, piece.y), (1 + 4, 10));
+}
@@ -0,0 +1,240 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use async_std::task::sleep;
+
+use fracas::{
+    catalogue::*, events::*, map::*, net::*, replay::*, server::*, utils::*, world::*, *,
+};
+
+mod common;
+use common::*;
+
+fn skirmish() -> Replay {
+    let mut replay = Replay::new(
+        99,
+        100,
+        WinCondition::LastTeamStanding,
+        Catalogue::built_in().clone(),
+        Map::built_in().clone(),
+    );
+    replay.record_spawn(0, Team::Green, "giant", 5);
+    replay.record_spawn(0, Team::Red, "barbarian", 5);
+    replay.record_spawn(50, Team::Red, "archer", 2);
+    replay.record_spawn(120, Team::Green, "barbarian", 9);
+    replay.length = 2_000;
+    replay
+}
+
+// a directory of its own for each test, so they can run side by side
+fn replay_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fracas-replays-{test}-{}", fastrand::u64(..)));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn wait_for_replay(path: &Path) -> Replay {
+    for _ in 0..500 {
+        if let Ok(replay) = Replay::load(path) {
+            return replay;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    panic!("no replay was written to {}", path.display());
+}
+
+#[test]
+fn playback_steps_the_same_world_the_server_did() {
+    let mut playback = Playback::new(skirmish());
+    while playback.step() {}
+    assert_eq!(playback.tick(), 2_000);
+
+    // the server spawns between steps, at the world tick it has reached
+    let mut world = World::with_seed(99);
+    world.spawn(generate_giant(10, Team::Green));
+    world.spawn(generate_barbarian(10, Team::Red));
+    while world.tick() < 50 {
+        world.step();
+    }
+    world.spawn(generate_archer(4, Team::Red));
+    while world.tick() < 120 {
+        world.step();
+    }
+    world.spawn(generate_barbarian(18, Team::Green));
+    while world.tick() < 2_000 {
+        world.step();
+    }
+
+    assert_eq!(playback.world().pieces(), world.pieces());
+}
+
+#[test]
+fn a_replay_can_be_kept_from_the_spawn_events() {
+    let mut replay = skirmish();
+    replay.spawns.clear();
+
+    let mut world = World::with_seed(99);
+    for (tick, piece) in [
+        (0, generate_giant(10, Team::Green)),
+        (0, generate_barbarian(10, Team::Red)),
+        (50, generate_archer(4, Team::Red)),
+        (120, generate_barbarian(18, Team::Green)),
+    ] {
+        world.spawn(piece);
+        replay.record(tick, &world.take_events());
+    }
+
+    assert_eq!(replay.spawns, skirmish().spawns);
+}
+
+#[test]
+fn seeking_lands_on_the_same_tick_either_way() {
+    let mut reference = Playback::new(skirmish());
+    reference.seek(500);
+    assert_eq!(reference.tick(), 500);
+
+    let mut playback = Playback::new(skirmish());
+    playback.seek(1_500);
+    playback.seek(500);
+    assert_eq!(playback.world().pieces(), reference.world().pieces());
+
+    // one step at a time gets to the same place as one big jump
+    for _ in 0..40 {
+        playback.step();
+        reference.step();
+    }
+    reference.seek(540);
+    assert_eq!(playback.world().pieces(), reference.world().pieces());
+
+    playback.seek(u64::MAX);
+    assert!(playback.at_end());
+    assert_eq!(playback.tick(), 2_000);
+    assert!(!playback.step());
+}
+
+#[test]
+fn replays_survive_a_trip_through_a_file() {
+    let dir = replay_dir("file");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("skirmish.ron");
+
+    std::fs::write(&path, skirmish().to_ron()).unwrap();
+    assert_eq!(Replay::load(&path).unwrap(), skirmish());
+
+    let mut broken = skirmish();
+    broken.spawns[2].unit = "dragon".to_string();
+    std::fs::write(&path, broken.to_ron()).unwrap();
+    assert!(matches!(
+        Replay::load(&path),
+        Err(ReplayError::UnknownUnit(unit)) if unit == "dragon"
+    ));
+
+    std::fs::write(&path, "(seed: 1)").unwrap();
+    assert!(matches!(Replay::load(&path), Err(ReplayError::Parse(_))));
+}
+
+#[async_std::test]
+async fn the_server_records_battles_that_play_back_exactly() {
+    // quick units so the armies meet and the dice get used before the time runs out
+    let mut catalogue = Catalogue::built_in().clone();
+    for unit in &mut catalogue.units {
+        unit.movement_rate = 1;
+    }
+    let dir = replay_dir("server");
+    let config = ServerConfig {
+        countdown_secs: 0,
+        win_condition: WinCondition::TimeLimit(300),
+        starting_gold: 1_000,
+        catalogue,
+        replay_dir: Some(dir.clone()),
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+    let (mut host, code) = host_game(address).await;
+    let mut joiner = join_game(address, &code).await;
+
+    let spawn = |unit: &str, row| ClientMessage::SpawnUnit {
+        unit: unit.to_string(),
+        row,
+    };
+    request(&mut host, &spawn("barbarian", 4)).await;
+    request(&mut joiner, &spawn("barbarian", 5)).await;
+
+    send_message(&mut host, &ClientMessage::Ready)
+        .await
+        .unwrap();
+    send_message(&mut joiner, &ClientMessage::Ready)
+        .await
+        .unwrap();
+    while !matches!(
+        next_reply(&mut host).await,
+        ServerMessage::Phase(MatchPhase::Running)
+    ) {}
+
+    // these land part way through the battle
+    for row in 1..=3 {
+        request(&mut host, &spawn("archer", row)).await;
+        request(&mut joiner, &spawn("giant", row + 5)).await;
+    }
+
+    let outcome = loop {
+        if let ServerMessage::Phase(MatchPhase::Finished(outcome)) = next_reply(&mut host).await {
+            break outcome;
+        }
+    };
+    let last = snapshot_where(&mut host, |_| true).await;
+
+    let replay = wait_for_replay(&dir.join(format!("{code}-0.ron"))).await;
+    assert!((1..=300).contains(&replay.length));
+    assert_eq!(replay.spawns.len(), 8);
+    assert!(replay.spawns[2..].iter().all(|s| s.tick > 0));
+
+    let mut playback = Playback::new(replay);
+    playback.seek(u64::MAX);
+    assert_eq!(playback.world().pieces(), &last[..]);
+    assert_eq!(
+        playback.world().outcome(playback.replay().win_condition),
+        Some(outcome)
+    );
+
+    // make sure it was a real fight and not just a march
+    let hurt = last.iter().any(|p| {
+        let unit = Catalogue::built_in()
+            .units
+            .iter()
+            .find(|u| u.denotation == p.denotation)
+            .unwrap();
+        p.hp < unit.hp
+    });
+    assert!(hurt);
+}
+
+#[async_std::test]
+async fn an_abandoned_battle_is_still_saved() {
+    let dir = replay_dir("abandoned");
+    let config = ServerConfig {
+        replay_dir: Some(dir.clone()),
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+
+    let (mut host, code) = host_game(address).await;
+    let giant = ClientMessage::SpawnUnit {
+        unit: "giant".to_string(),
+        row: 7,
+    };
+    request(&mut host, &giant).await;
+    drop(host);
+
+    let replay = wait_for_replay(&dir.join(format!("{code}-0.ron"))).await;
+    assert_eq!(replay.spawns.len(), 1);
+    assert_eq!(replay.spawns[0].unit, "giant");
+
+    // a session nobody spawned into leaves nothing behind
+    let (empty, code) = host_game(address).await;
+    drop(empty);
+    sleep(Duration::from_millis(200)).await;
+    assert!(!dir.join(format!("{code}-0.ron")).exists());
+}
@@ -0,0 +1,148 @@
+use async_std::net::TcpStream;
+
+use fracas::*;
+
+mod common;
+use common::*;
+
+fn barbarian(row: u8) -> ClientMessage {
+    ClientMessage::SpawnUnit {
+        unit: "barbarian".to_string(),
+        row,
+    }
+}
+
+#[async_std::test]
+async fn sessions_are_isolated_from_each_other() {
+    let address = start_server().await;
+    let (mut first, first_code) = host_game(address).await;
+    let (mut second, second_code) = host_game(address).await;
+    assert_ne!(first_code, second_code);
+
+    for row in 1..=3 {
+        request(&mut first, &barbarian(row)).await;
+    }
+    request(&mut second, &barbarian(9)).await;
+
+    let pieces = snapshot_where(&mut first, |p| p.len() == 3).await;
+    assert!(pieces.iter().all(|p| p.y <= 8));
+
+    let pieces = snapshot_where(&mut second, |p| !p.is_empty()).await;
+    assert_eq!(pieces.len(), 1);
+    assert_eq!(pieces[0].y, 18);
+}
+
+#[async_std::test]
+async fn joiners_share_the_hosts_battle() {
+    let address = start_server().await;
+    let (mut host, code) = host_game(address).await;
+    let mut joiner = join_game(address, &code).await;
+
+    request(&mut host, &barbarian(2)).await;
+    request(&mut joiner, &barbarian(8)).await;
+
+    for stream in [&mut host, &mut joiner] {
+        let pieces = snapshot_where(stream, |p| p.len() == 2).await;
+        assert!(pieces.iter().any(|p| p.team == Team::Green));
+        assert!(pieces.iter().any(|p| p.team == Team::Red));
+    }
+}
+
+#[async_std::test]
+async fn unknown_codes_are_rejected() {
+    let address = start_server().await;
+    let mut stream = TcpStream::connect(address).await.unwrap();
+
+    let join = ClientMessage::JoinGame {
+        code: "nope".to_string(),
+    };
+    match request(&mut stream, &join).await {
+        ServerMessage::Error(ServerError::UnknownSession(code)) => assert_eq!(code, "nope"),
+        reply => panic!("expected an unknown session error, got {:?}", reply),
+    }
+}
+
+#[async_std::test]
+async fn playing_needs_a_session() {
+    let address = start_server().await;
+    let mut stream = TcpStream::connect(address).await.unwrap();
+
+    let reply = request(&mut stream, &barbarian(1)).await;
+    assert!(matches!(
+        reply,
+        ServerMessage::Error(ServerError::NotInSession)
+    ));
+    let reply = request(&mut stream, &ClientMessage::RequestSnapshot).await;
+    assert!(matches!(
+        reply,
+        ServerMessage::Error(ServerError::NotInSession)
+    ));
+}
+
+#[async_std::test]
+async fn a_connection_stays_in_its_session() {
+    let address = start_server().await;
+    let (_other, other_code) = host_game(address).await;
+    let (mut stream, _) = host_game(address).await;
+
+    let reply = request(&mut stream, &ClientMessage::NewGame { map: None }).await;
+    assert!(matches!(
+        reply,
+        ServerMessage::Error(ServerError::AlreadyInSession)
+    ));
+    let reply = request(&mut stream, &ClientMessage::JoinGame { code: other_code }).await;
+    assert!(matches!(
+        reply,
+        ServerMessage::Error(ServerError::AlreadyInSession)
+    ));
+}
+
+#[async_std::test]
+async fn teams_are_handed_out_by_the_server() {
+    let address = start_server().await;
+    let mut host = TcpStream::connect(address).await.unwrap();
+    let (code, host_player) = match request(&mut host, &ClientMessage::NewGame { map: None }).await
+    {
+        ServerMessage::GameCreated {
+            code, player, team, ..
+        } => {
+            assert_eq!(team, Team::Green);
+            (code, player)
+        }
+        reply => panic!("expected a new game, got {:?}", reply),
+    };
+
+    let mut teams = vec![];
+    let mut players = vec![host_player];
+    for _ in 0..3 {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        match request(&mut stream, &ClientMessage::JoinGame { code: code.clone() }).await {
+            ServerMessage::GameJoined { player, team, .. } => {
+                teams.push(team);
+                players.push(player);
+            }
+            reply => panic!("expected to join, got {:?}", reply),
+        }
+    }
+
+    // joiners fill whichever side is short handed
+    assert_eq!(teams, vec![Team::Red, Team::Green, Team::Red]);
+    players.dedup();
+    assert_eq!(players.len(), 4);
+}
+
+#[async_std::test]
+async fn spawns_fight_for_the_side_the_server_assigned() {
+    let address = start_server().await;
+    let (mut host, code) = host_game(address).await;
+    let mut joiner = join_game(address, &code).await;
+
+    // the same request lands on each player's own side of the field
+    request(&mut host, &barbarian(1)).await;
+    request(&mut joiner, &barbarian(1)).await;
+    let mut pieces = snapshot_where(&mut joiner, |p| p.len() == 2).await;
+    pieces.sort_by_key(|p| p.unique_id);
+    let teams: Vec<Team> = pieces.iter().map(|p| p.team).collect();
+    assert_eq!(teams, [Team::Green, Team::Red]);
+    assert!(pieces[0].x < pieces[1].x);
+}
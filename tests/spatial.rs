@@ -0,0 +1,77 @@
+use fracas::{
+    spatial::*,
+    terrain::{FIELD_HEIGHT, FIELD_WIDTH},
+    utils::*,
+    Character, Team,
+};
+
+fn at(x: i16, y: i16, colour: Team) -> Character {
+    let mut piece = generate_barbarian(y, colour);
+    piece.x = x;
+    piece
+}
+
+#[test]
+fn the_nearest_enemy_is_found_the_way_it_always_was() {
+    let mut pieces = vec![
+        at(10, 10, Team::Green),
+        at(12, 11, Team::Red),
+        at(11, 11, Team::Green),
+        at(9, 12, Team::Red),
+    ];
+    let index = SpatialIndex::build(&pieces, FIELD_WIDTH, FIELD_HEIGHT);
+    assert_eq!(index.nearest_enemy(&pieces, 0), Some(1));
+    assert_eq!(index.nearest_enemy(&pieces, 1), Some(2));
+
+    // anyone on the same row wins however far off, the first of them if there are several
+    pieces.push(at(60, 10, Team::Red));
+    pieces.push(at(2, 10, Team::Red));
+    let index = SpatialIndex::build(&pieces, FIELD_WIDTH, FIELD_HEIGHT);
+    assert_eq!(index.nearest_enemy(&pieces, 0), Some(4));
+
+    // the dead are not in the index at all
+    pieces[4].hp = 0;
+    pieces[5].hp = 0;
+    pieces[1].hp = 0;
+    let index = SpatialIndex::build(&pieces, FIELD_WIDTH, FIELD_HEIGHT);
+    assert_eq!(index.nearest_enemy(&pieces, 0), Some(3));
+
+    pieces[3].hp = 0;
+    let index = SpatialIndex::build(&pieces, FIELD_WIDTH, FIELD_HEIGHT);
+    assert_eq!(index.nearest_enemy(&pieces, 0), None);
+}
+
+#[test]
+fn range_queries_come_back_in_piece_order() {
+    let pieces = vec![
+        at(20, 5, Team::Red),
+        at(15, 5, Team::Green),
+        at(17, 3, Team::Red),
+        at(18, 9, Team::Red),
+        at(17, 5, Team::Red),
+    ];
+    let index = SpatialIndex::build(&pieces, FIELD_WIDTH, FIELD_HEIGHT);
+
+    assert_eq!(index.within(&pieces, 15, 5, 2), vec![1, 2, 4]);
+    assert_eq!(index.within(&pieces, 15, 5, 5), vec![0, 1, 2, 3, 4]);
+    assert_eq!(index.within(&pieces, 15, 5, 0), vec![1]);
+}
+
+#[test]
+fn the_index_follows_units_about() {
+    let pieces = vec![at(3, 3, Team::Green), at(3, 3, Team::Red)];
+    let mut index = SpatialIndex::build(&pieces, FIELD_WIDTH, FIELD_HEIGHT);
+    assert_eq!(index.at(3, 3).len(), 2);
+
+    index.relocate(0, (3, 3), (4, 4));
+    assert_eq!(index.at(4, 4), &[0]);
+    assert_eq!(index.at(3, 3), &[1]);
+
+    index.remove(1, &pieces[1]);
+    assert!(!index.occupied(3, 3));
+    assert!(index.occupied(4, 4));
+    assert!(!index.occupied(-1, 3));
+
+    // with the only enemy gone there is nobody left to find
+    assert_eq!(index.nearest_enemy(&pieces, 0), None);
+}
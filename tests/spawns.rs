@@ -0,0 +1,162 @@
+use async_std::net::TcpStream;
+
+use std::net::SocketAddr;
+
+use fracas::{catalogue::*, map::*, net::*, server::*, *};
+
+mod common;
+use common::*;
+
+fn spawn(unit: &str, row: u8) -> ClientMessage {
+    ClientMessage::SpawnUnit {
+        unit: unit.to_string(),
+        row,
+    }
+}
+
+// enough gold that the price never gets a say in what is accepted
+async fn rich_server() -> SocketAddr {
+    start_server_with(ServerConfig {
+        starting_gold: 10_000,
+        ..ServerConfig::default()
+    })
+    .await
+}
+
+async fn rejection(stream: &mut TcpStream, message: &ClientMessage) -> ServerError {
+    match request(stream, message).await {
+        ServerMessage::Error(e) => e,
+        reply => panic!("expected {:?} to be rejected, got {:?}", message, reply),
+    }
+}
+
+async fn raw_request(stream: &mut TcpStream, frame: &[u8]) -> ServerMessage {
+    write_frame(stream, frame).await.unwrap();
+    next_reply(stream).await
+}
+
+async fn raw_rejection(stream: &mut TcpStream, frame: &[u8]) -> ServerError {
+    match raw_request(stream, frame).await {
+        ServerMessage::Error(e) => e,
+        reply => panic!("expected {:?} to be rejected, got {:?}", frame, reply),
+    }
+}
+
+#[async_std::test]
+async fn rows_outside_the_field_are_rejected() {
+    let address = rich_server().await;
+    let (mut host, _) = host_game(address).await;
+
+    for row in [0, 10, 11, 48, 126, 255] {
+        assert_eq!(
+            rejection(&mut host, &spawn("barbarian", row)).await,
+            ServerError::RowOutOfRange(row)
+        );
+    }
+
+    // the first and last of the map's spawn points are fine
+    let last = Map::built_in().spawn_points(Team::Green).len() as u8;
+    for row in [1, last] {
+        assert!(matches!(
+            request(&mut host, &spawn("giant", row)).await,
+            ServerMessage::SpawnAccepted
+        ));
+    }
+
+    // only the two accepted spawns ever reached the battle, and both are on the field
+    send_message(&mut host, &ClientMessage::RequestSnapshot)
+        .await
+        .unwrap();
+    let pieces = snapshot_where(&mut host, |_| true).await;
+    assert_eq!(pieces.len(), 2);
+    assert!(pieces.iter().all(|p| (0..20).contains(&p.y)));
+}
+
+#[async_std::test]
+async fn spawns_are_checked_in_order() {
+    let address = start_server().await;
+
+    // no session beats every other problem with the request
+    let mut stranger = TcpStream::connect(address).await.unwrap();
+    assert_eq!(
+        rejection(&mut stranger, &spawn("archer", 0)).await,
+        ServerError::NotInSession
+    );
+
+    // then the row, then the unit
+    let (mut host, _) = host_game(address).await;
+    assert_eq!(
+        rejection(&mut host, &spawn("dragon", 99)).await,
+        ServerError::RowOutOfRange(99)
+    );
+    assert_eq!(
+        rejection(&mut host, &spawn("dragon", 5)).await,
+        ServerError::UnknownUnit("dragon".to_string())
+    );
+}
+
+#[async_std::test]
+async fn only_catalogue_units_can_be_spawned() {
+    let address = rich_server().await;
+    let (mut host, _) = host_game(address).await;
+
+    // names are matched exactly, keys and denotations are not names
+    for unit in [
+        "",
+        "Barbarian",
+        "barbarian ",
+        "b",
+        "G",
+        &"giant".repeat(1000),
+    ] {
+        assert_eq!(
+            rejection(&mut host, &spawn(unit, 5)).await,
+            ServerError::UnknownUnit(unit.to_string())
+        );
+    }
+
+    for unit in &Catalogue::built_in().units {
+        assert!(matches!(
+            request(&mut host, &spawn(&unit.name, 5)).await,
+            ServerMessage::SpawnAccepted
+        ));
+    }
+}
+
+#[async_std::test]
+async fn undecodable_spawns_are_malformed() {
+    let address = start_server().await;
+    let (mut host, _) = host_game(address).await;
+
+    let valid = bincode::serialize(&spawn("barbarian", 3)).unwrap();
+    // the unit name is the last field but one, a u64 length and its bytes followed by the row byte
+    let name_at = valid.len() - 1 - "barbarian".len();
+    let length_at = name_at - 8;
+
+    let mut huge_name = valid.clone();
+    huge_name[length_at..name_at].copy_from_slice(&u64::MAX.to_le_bytes());
+    let mut not_utf8 = valid.clone();
+    not_utf8[name_at] = 0xff;
+    let truncated = valid[..valid.len() - 1].to_vec();
+
+    // including what the old text protocol used to send for a spawn
+    for frame in [
+        huge_name,
+        not_utf8,
+        truncated,
+        b"rb~".to_vec(),
+        b"g\xff\xfe".to_vec(),
+        vec![0xff; 9],
+    ] {
+        assert!(matches!(
+            raw_rejection(&mut host, &frame).await,
+            ServerError::Malformed(_)
+        ));
+    }
+
+    // none of it broke the connection
+    assert!(matches!(
+        raw_request(&mut host, &valid).await,
+        ServerMessage::SpawnAccepted
+    ));
+}
@@ -0,0 +1,190 @@
+use fracas::{events::*, map::*, net::*, server::*, terrain::*, utils::*, world::*, *};
+
+mod common;
+use common::*;
+
+// a unit that never gets round to moving
+fn post(piece: Character, x: i16) -> Character {
+    Character {
+        x,
+        movement_rate: i16::MAX,
+        movement_cooldown: i16::MAX,
+        ..piece
+    }
+}
+
+fn runner(x: i16, y: i16, rate: i16) -> Character {
+    Character {
+        x,
+        movement_rate: rate,
+        movement_cooldown: 1,
+        ..generate_barbarian(y, Team::Green)
+    }
+}
+
+fn world_on(terrain: Terrain) -> World {
+    let mut world = World::with_seed(21);
+    world.set_terrain(terrain);
+    world
+}
+
+#[test]
+fn terrain_is_written_as_rows_of_tile_codes() {
+    let mut terrain = Terrain::default();
+    terrain.set(0, 0, Tile::Wall);
+    terrain.set(5, 3, Tile::Forest);
+    terrain.set(69, 19, Tile::Water);
+    terrain.set(70, 19, Tile::Road);
+
+    let rows = terrain.rows();
+    assert_eq!(rows.len(), FIELD_HEIGHT as usize);
+    assert!(rows[0].starts_with("#....."));
+    assert_eq!(&rows[3][4..7], ".^.");
+    assert!(rows[19].ends_with(".~"));
+    assert_eq!(Terrain::parse(&rows), Ok(terrain.clone()));
+    assert_eq!(terrain.tile(-1, 0), Tile::Wall);
+
+    let text = ron::to_string(&terrain).unwrap();
+    assert_eq!(ron::from_str::<Terrain>(&text).unwrap(), terrain);
+    let bytes = bincode::serialize(&terrain).unwrap();
+    assert_eq!(bincode::deserialize::<Terrain>(&bytes).unwrap(), terrain);
+
+    assert_eq!(Terrain::parse(&rows[1..]).unwrap().height(), 19);
+    assert_eq!(Terrain::parse::<String>(&[]), Err(TerrainError::Empty));
+    let mut short = rows.clone();
+    short[4].pop();
+    assert_eq!(Terrain::parse(&short), Err(TerrainError::RowLength(4, 69)));
+    let mut odd = rows;
+    odd[7].replace_range(0..1, "?");
+    assert_eq!(Terrain::parse(&odd), Err(TerrainError::UnknownTile('?')));
+}
+
+#[test]
+fn units_go_round_water_to_the_ford() {
+    let mut terrain = Terrain::default();
+    for y in 0..FIELD_HEIGHT {
+        terrain.set(20, y, Tile::Water);
+    }
+    terrain.set(20, 3, Tile::Road);
+
+    let mut world = world_on(terrain);
+    let id = world.spawn(runner(15, 10, 1)).unwrap();
+    world.spawn(post(generate_giant(10, Team::Red), 30));
+
+    for _ in 0..60 {
+        world.step();
+        let piece = world.piece(id).unwrap();
+        assert!(world.terrain().passable(piece.x, piece.y));
+    }
+
+    let piece = world.piece(id).unwrap();
+    assert_eq!((piece.x, piece.y), (29, 10));
+}
+
+#[test]
+fn roads_are_quicker_going() {
+    let mut road = Terrain::default();
+    for x in 0..FIELD_WIDTH {
+        road.set(x, 10, Tile::Road);
+    }
+
+    let mut distances = Vec::new();
+    for terrain in [Terrain::default(), road] {
+        let mut world = world_on(terrain);
+        let id = world.spawn(runner(10, 10, 4)).unwrap();
+        world.spawn(post(generate_giant(10, Team::Red), 60));
+        for _ in 0..8 {
+            world.step();
+        }
+        distances.push(world.piece(id).unwrap().x - 10);
+    }
+
+    assert_eq!(distances, vec![2, 4]);
+}
+
+#[test]
+fn walls_block_arrows_but_water_does_not() {
+    let mut shots = Vec::new();
+    for tile in [Tile::Wall, Tile::Water, Tile::Open] {
+        let mut terrain = Terrain::default();
+        terrain.set(11, 10, tile);
+        terrain.set(12, 10, tile);
+
+        let mut world = world_on(terrain);
+        world.spawn(post(generate_archer(10, Team::Green), 10));
+        world.spawn(post(generate_giant(10, Team::Red), 13));
+        for _ in 0..40 {
+            world.step();
+        }
+        shots.push(
+            world
+                .take_events()
+                .iter()
+                .any(|e| e.kind() == EventKind::AttackStarted),
+        );
+    }
+
+    assert_eq!(shots, vec![false, true, true]);
+}
+
+#[test]
+fn forest_makes_units_harder_to_hit() {
+    let mut terrain = Terrain::default();
+    terrain.set(11, 10, Tile::Forest);
+
+    let mut world = world_on(terrain);
+    world.spawn(post(generate_barbarian(10, Team::Green), 10));
+    world.spawn(post(generate_giant(10, Team::Red), 11));
+    for _ in 0..40 {
+        world.step();
+    }
+
+    // the giant's own defence is 12, the trees are worth another 2
+    let defences: Vec<i16> = world
+        .take_events()
+        .iter()
+        .filter_map(|e| match e {
+            CombatEvent::Rolled { defence, .. } => Some(*defence),
+            _ => None,
+        })
+        .collect();
+    assert!(defences.contains(&14));
+    assert!(!defences.contains(&12));
+}
+
+#[async_std::test]
+async fn the_server_sends_the_terrain_of_its_map() {
+    let mut map = Map::built_in().clone();
+    map.tiles.set(30, 10, Tile::Forest);
+    map.tiles.set(31, 10, Tile::Water);
+    let terrain = map.tiles.clone();
+    let config = ServerConfig {
+        maps: vec![map],
+        ..ServerConfig::default()
+    };
+    let address = start_server_with(config).await;
+    let (mut host, _) = host_game(address).await;
+
+    let spawn = |row| ClientMessage::SpawnUnit {
+        unit: "barbarian".to_string(),
+        row,
+    };
+    assert!(matches!(
+        request(&mut host, &spawn(4)).await,
+        ServerMessage::SpawnAccepted
+    ));
+
+    loop {
+        if let ServerMessage::Snapshot {
+            terrain: sent,
+            pieces,
+            ..
+        } = recv_message(&mut host).await.unwrap()
+        {
+            if !pieces.is_empty() {
+                assert_eq!(sent, terrain);
+                break;
+            }
+        }
+    }
+}
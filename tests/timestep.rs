@@ -0,0 +1,102 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use async_std::{net::TcpStream, task::sleep};
+
+use fracas::{net::*, timestep::*, *};
+
+mod common;
+use common::*;
+
+const TICK: Duration = Duration::from_millis(10);
+
+#[test]
+fn nothing_is_due_before_the_first_tick() {
+    let start = Instant::now();
+    let mut timestep = FixedTimestep::new(TICK, start, 5);
+
+    assert_eq!(timestep.due(start), 0);
+    assert_eq!(timestep.due(start + TICK / 2), 0);
+    assert_eq!(timestep.next_tick(), start + TICK);
+}
+
+#[test]
+fn late_wake_ups_do_not_shift_the_schedule() {
+    let start = Instant::now();
+    let mut timestep = FixedTimestep::new(TICK, start, 5);
+
+    // waking 3ms late still leaves the next tick on the original grid
+    assert_eq!(timestep.due(start + TICK + Duration::from_millis(3)), 1);
+    assert_eq!(timestep.next_tick(), start + TICK * 2);
+    assert_eq!(timestep.due(start + TICK * 2), 1);
+    assert_eq!(timestep.next_tick(), start + TICK * 3);
+}
+
+#[test]
+fn missed_ticks_are_caught_up() {
+    let start = Instant::now();
+    let mut timestep = FixedTimestep::new(TICK, start, 5);
+
+    assert_eq!(timestep.due(start + TICK * 3), 3);
+    assert_eq!(timestep.next_tick(), start + TICK * 4);
+}
+
+#[test]
+fn catching_up_is_bounded() {
+    let start = Instant::now();
+    let mut timestep = FixedTimestep::new(TICK, start, 5);
+
+    // a long stall runs a handful of steps and drops the rest instead of fast forwarding
+    assert_eq!(timestep.due(start + TICK * 50), 5);
+    assert_eq!(timestep.next_tick(), start + TICK * 51);
+    assert_eq!(timestep.due(start + TICK * 51), 1);
+}
+
+#[test]
+fn waking_up_for_other_reasons_does_not_delay_the_ticks() {
+    let start = Instant::now();
+    let mut timestep = FixedTimestep::new(TICK, start, 5);
+
+    // a wake-up every millisecond, as a stream of connections would cause
+    let ticks: u32 = (1..=500)
+        .map(|ms| timestep.due(start + Duration::from_millis(ms)))
+        .sum();
+    assert_eq!(ticks, 50);
+}
+
+// a fresh watcher only ever sees snapshots pushed after it joined, never a stale backlog
+async fn current_tick(address: SocketAddr, code: &str) -> u64 {
+    let mut watcher = join_game(address, code).await;
+    loop {
+        if let ServerMessage::Snapshot { tick, .. } = recv_message(&mut watcher).await.unwrap() {
+            return tick;
+        }
+    }
+}
+
+#[async_std::test]
+async fn connection_churn_does_not_slow_the_simulation() {
+    let address = start_server().await;
+    let (_host, code) = host_game(address).await;
+
+    let started = Instant::now();
+    let first = current_tick(address, &code).await;
+
+    // the old loop restarted its 10ms timer on every accept, so this used to stall the battle
+    while started.elapsed() < Duration::from_millis(500) {
+        let _ = TcpStream::connect(address).await.unwrap();
+        sleep(Duration::from_millis(1)).await;
+    }
+
+    // a busy machine can lose a good few ticks to the catch-up limit, the stall lost nearly all of them
+    let last = current_tick(address, &code).await;
+    let expected = started.elapsed().as_millis() as u64 / 10;
+    let ticks = last - first;
+    assert!(
+        ticks * 2 >= expected && ticks <= expected * 2,
+        "{ticks} ticks in {:?}",
+        started.elapsed()
+    );
+}
@@ -0,0 +1,171 @@
+use fracas::{events::*, utils::*, world::*, Team};
+
+#[test]
+fn spawn_hands_out_unique_ids() {
+    let mut world = World::new();
+    let ids: Vec<Option<u16>> = (0..5)
+        .map(|row| world.spawn(generate_archer(row * 2, Team::Green)))
+        .collect();
+
+    assert_eq!(ids, [0, 1, 2, 3, 4].map(Some));
+    assert_eq!(world.piece(3).unwrap().y, 6);
+    assert!(world.piece(5).is_none());
+}
+
+#[test]
+fn a_lone_team_stands_still() {
+    let mut world = World::new();
+    world.spawn(generate_barbarian(2, Team::Green));
+    world.spawn(generate_barbarian(4, Team::Green));
+
+    for _ in 0..500 {
+        world.step();
+    }
+
+    assert_eq!(world.tick(), 500);
+    assert!(world.pieces().iter().all(|p| p.x == 1 && p.hp == 12));
+    let kinds: Vec<EventKind> = world.take_events().iter().map(|e| e.kind()).collect();
+    assert_eq!(kinds, [EventKind::Spawned, EventKind::Spawned]);
+}
+
+#[test]
+fn opposing_units_close_in_and_fight_to_the_end() {
+    let mut world = World::with_rng(fastrand::Rng::with_seed(42));
+    world.spawn(generate_giant(10, Team::Green));
+    world.spawn(generate_barbarian(10, Team::Red));
+
+    while world.living_count(Team::Green) > 0 && world.living_count(Team::Red) > 0 {
+        world.step();
+        assert!(world.tick() < 100_000, "the battle never finished");
+    }
+
+    assert_eq!(world.living().count(), 1);
+    let events = world.take_events();
+    let killed = events.iter().filter(|e| e.kind() == EventKind::Killed);
+    assert_eq!(killed.count(), 1);
+    assert!(world.take_events().is_empty());
+}
+
+#[test]
+fn the_fallen_stay_down_for_one_tick() {
+    let mut world = World::with_seed(42);
+    world.spawn(generate_giant(10, Team::Green));
+    world.spawn(generate_archer(10, Team::Red));
+
+    while world.living_count(Team::Red) > 0 {
+        world.step();
+        assert!(world.tick() < 100_000, "the battle never finished");
+    }
+
+    // still there to be seen lying down, then gone on the next tick
+    assert_eq!(world.pieces().len(), 2);
+    assert!(world.piece(1).unwrap().hp <= 0);
+    world.step();
+    let ids: Vec<u16> = world.pieces().iter().map(|p| p.unique_id).collect();
+    assert_eq!(ids, [0]);
+}
+
+#[test]
+fn ids_still_on_the_field_are_not_handed_out_again() {
+    let mut world = World::new();
+    assert_eq!(world.spawn(generate_giant(10, Team::Green)), Some(0));
+
+    // run the ids all the way round with units that are cleared away as soon as they land
+    let mut fallen = generate_archer(2, Team::Red);
+    fallen.hp = 0;
+    for i in 1..=u16::MAX {
+        assert_eq!(world.spawn(fallen.clone()), Some(i));
+        if i % 100 == 0 {
+            world.step();
+        }
+    }
+    world.step();
+
+    // the giant is still using 0
+    assert_eq!(world.spawn(generate_archer(4, Team::Red)), Some(1));
+    assert_eq!(world.piece(0).unwrap().denotation, 'G');
+}
+
+fn skirmish(seed: u64) -> World {
+    let mut world = World::with_seed(seed);
+    for row in 1..=9 {
+        world.spawn(generate_barbarian(row * 2, Team::Green));
+        world.spawn(generate_archer(row * 2, Team::Red));
+    }
+    world.spawn(generate_giant(10, Team::Red));
+    world.spawn(generate_giant(8, Team::Green));
+
+    for _ in 0..3_000 {
+        world.step();
+    }
+    world
+}
+
+#[test]
+fn the_same_seed_replays_the_same_battle() {
+    let first = skirmish(1234);
+    let second = skirmish(1234);
+
+    assert_eq!(first.seed(), Some(1234));
+    assert_eq!(first.pieces(), second.pieces());
+
+    // make sure the scenario actually involved some dice, the fallen are cleared away
+    assert!(first.pieces().len() < 20);
+}
+
+#[test]
+fn different_seeds_play_out_differently() {
+    let outcomes: Vec<Vec<i16>> = (0..5)
+        .map(|seed| skirmish(seed).pieces().iter().map(|p| p.hp).collect())
+        .collect();
+
+    assert!(outcomes.iter().any(|hp| *hp != outcomes[0]));
+}
+
+#[test]
+fn the_last_team_standing_wins() {
+    let mut world = World::with_seed(42);
+    world.spawn(generate_giant(10, Team::Green));
+    assert_eq!(world.outcome(WinCondition::LastTeamStanding), None);
+
+    // red has not lost just because it had nobody on the field yet
+    world.step();
+    assert_eq!(world.outcome(WinCondition::LastTeamStanding), None);
+
+    world.spawn(generate_barbarian(10, Team::Red));
+    while world.outcome(WinCondition::LastTeamStanding).is_none() {
+        world.step();
+        assert!(world.tick() < 100_000, "the battle never finished");
+    }
+
+    let survivor = world.living().next().unwrap().team;
+    assert_eq!(
+        world.outcome(WinCondition::LastTeamStanding),
+        Some(Outcome::Won(survivor))
+    );
+}
+
+#[test]
+fn a_time_limit_goes_to_the_bigger_side() {
+    let mut world = World::with_seed(42);
+    world.spawn(generate_barbarian(2, Team::Green));
+    world.spawn(generate_barbarian(4, Team::Green));
+    world.spawn(generate_barbarian(18, Team::Red));
+
+    // still far apart when the time runs out
+    for _ in 0..9 {
+        world.step();
+    }
+    assert_eq!(world.outcome(WinCondition::TimeLimit(10)), None);
+    world.step();
+    assert_eq!(
+        world.outcome(WinCondition::TimeLimit(10)),
+        Some(Outcome::Won(Team::Green))
+    );
+
+    world.spawn(generate_barbarian(16, Team::Red));
+    assert_eq!(
+        world.outcome(WinCondition::TimeLimit(10)),
+        Some(Outcome::Draw)
+    );
+}